@@ -0,0 +1,102 @@
+//! Frames per second from a client socket, through `Server`, into the engine queue, over
+//! loopback. The client joins first, since the server only passes on movement from players.
+
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use lib::config::Config;
+use lib::engine::Message;
+use lib::server::Server;
+use net::frame::Frame;
+use net::packet::join::Join;
+use net::packet::movement::Movement;
+use net::packet::Packet;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::net::UdpSocket;
+use tokio::runtime::Runtime;
+use tokio::sync::mpsc::{channel, Receiver};
+use tokio::time::timeout;
+
+/// Small enough that a burst fits in the socket buffers, so nothing is lost on loopback.
+const BATCH: u32 = 64;
+
+struct Loopback {
+    client: UdpSocket,
+    engine_rx: Receiver<Message>,
+    syn: u32,
+    buf: Vec<u8>,
+}
+
+impl Loopback {
+    async fn start() -> Self {
+        let config = Config {
+            addr: "127.0.0.1:0".parse().unwrap(),
+            ..Default::default()
+        };
+        let (engine_tx, mut engine_rx) = channel(config.engine_queue_capacity);
+        let mut server = Server::from_config(config).await;
+        server.engine_tx = Some(Arc::new(engine_tx));
+        let target = server.local_addr().unwrap();
+        tokio::spawn(async move { server.run().await });
+
+        let client = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        client.connect(target).await.unwrap();
+        let mut join = Frame::new();
+        join.packet = Some(Packet::Join(Join::default()));
+        client.send(&join.to_bytes()).await.unwrap();
+        match timeout(Duration::from_secs(1), engine_rx.recv()).await {
+            Ok(Some(Message::Join { .. })) => {}
+            other => panic!("Expected the join to reach the engine, got {other:?}"),
+        }
+
+        Self {
+            client,
+            engine_rx,
+            syn: 1,
+            buf: Vec::with_capacity(64),
+        }
+    }
+
+    /// Sends a batch of frames and waits for the engine side to receive all of them, panicking if
+    /// any are lost, which would make the timing meaningless.
+    async fn round_trip(&mut self) {
+        let mut frame = Frame::new();
+        frame.packet = Some(Packet::Movement(Movement::default()));
+        for _ in 0..BATCH {
+            frame.syn = self.syn;
+            self.syn = self.syn.wrapping_add(1);
+            self.buf.clear();
+            frame.encode(&mut self.buf);
+            self.client.send(&self.buf).await.unwrap();
+        }
+
+        let mut received = 0;
+        while received < BATCH {
+            match timeout(Duration::from_secs(1), self.engine_rx.recv()).await {
+                Ok(Some(_)) => received += 1,
+                _ => break,
+            }
+        }
+        assert_eq!(received, BATCH, "frames lost on loopback");
+    }
+}
+
+fn loopback(c: &mut Criterion) {
+    let runtime = Runtime::new().unwrap();
+    let mut loopback = runtime.block_on(Loopback::start());
+
+    let mut group = c.benchmark_group("loopback");
+    group.throughput(Throughput::Elements(BATCH as u64));
+    group.bench_function("frames", |b| {
+        b.iter_custom(|iterations| {
+            let start = Instant::now();
+            for _ in 0..iterations {
+                runtime.block_on(loopback.round_trip());
+            }
+            start.elapsed()
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, loopback);
+criterion_main!(benches);
@@ -0,0 +1,167 @@
+//! Inbound packets per second handled by the server receive loop on a single core, compared
+//! with the previous design that spawned a task and locked a mutex for every datagram. Both get
+//! the same work: every peer joins, then floods movement, which is what reaches the engine.
+//!
+//! Run with `cargo bench --bench receive`.
+
+use lib::backpressure::{Outbox, OverloadPolicy, OverloadStats};
+use lib::config::Config;
+use lib::engine::Message;
+use lib::server::{Connection, Server};
+use net::frame::Frame;
+use net::id::Id;
+use net::packet::join::Join;
+use net::packet::movement::Movement;
+use net::packet::Packet;
+use std::collections::HashMap;
+use std::net::{SocketAddr, UdpSocket as StdUdpSocket};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+use tokio::net::UdpSocket;
+use tokio::sync::mpsc::{channel, Receiver, Sender};
+use tokio::sync::Mutex;
+
+const WARM_UP: Duration = Duration::from_millis(200);
+const MEASURE: Duration = Duration::from_secs(2);
+const QUEUE_CAPACITY: usize = 1 << 16;
+const PEERS: usize = 4;
+
+/// Binds a peer and asks `target` to join, before any flooding starts so the join is not lost.
+fn join(target: SocketAddr) -> StdUdpSocket {
+    let socket = StdUdpSocket::bind("127.0.0.1:0").unwrap();
+    let mut frame = Frame::new();
+    frame.packet = Some(Packet::Join(Join::default()));
+    socket.send_to(&frame.to_bytes(), target).unwrap();
+    socket
+}
+
+/// Sends movement frames from `socket` to `target` as fast as the socket allows until `stop` is
+/// set.
+fn flood(
+    socket: StdUdpSocket,
+    target: SocketAddr,
+    stop: Arc<AtomicBool>,
+) -> thread::JoinHandle<()> {
+    thread::spawn(move || {
+        let mut frame = Frame::new();
+        frame.syn = 1;
+        frame.packet = Some(Packet::Movement(Movement::default()));
+        while !stop.load(Ordering::Relaxed) {
+            let bytes = frame.to_bytes();
+            let _ = socket.send_to(&bytes, target);
+            frame.syn = frame.syn.wrapping_add(1);
+        }
+    })
+}
+
+/// Stands in for the engine, on its own thread so that the runtime only does receive work.
+fn count(mut rx: Receiver<Message>, counter: Arc<AtomicU64>) -> thread::JoinHandle<()> {
+    thread::spawn(move || {
+        while rx.blocking_recv().is_some() {
+            counter.fetch_add(1, Ordering::Relaxed);
+        }
+    })
+}
+
+fn outbox() -> Outbox {
+    Outbox::new(
+        QUEUE_CAPACITY,
+        OverloadPolicy::DropOldest,
+        Arc::new(OverloadStats::default()),
+    )
+}
+
+async fn spawn_per_datagram(socket: UdpSocket, engine_tx: Arc<Sender<Message>>) {
+    let mut connections: HashMap<SocketAddr, Arc<Mutex<Connection>>> = HashMap::new();
+    loop {
+        let mut buf = [0; 64];
+        let Ok((n, origin)) = socket.recv_from(&mut buf).await else {
+            continue;
+        };
+
+        let connection = connections.entry(origin).or_insert_with(|| {
+            let mut connection = Connection::new(origin, outbox());
+            connection.engine_tx = Some(engine_tx.clone());
+            Arc::new(Mutex::new(connection))
+        });
+
+        if let Some(frame) = Frame::from_bytes(&buf[..n]) {
+            let connection = connection.clone();
+            let id = Id(connections.len() as u16);
+            tokio::spawn(async move {
+                let mut connection = connection.lock().await;
+                if let Some(Packet::Join(join)) = frame.packet {
+                    connection.join(id, 0, join.nickname);
+                }
+                let _ = connection.handle_frame(frame);
+            });
+        }
+    }
+}
+
+fn measure(name: &str, serve: impl FnOnce(Sender<Message>) -> SocketAddr) {
+    let counter = Arc::new(AtomicU64::new(0));
+    let (engine_tx, engine_rx) = channel(QUEUE_CAPACITY);
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .unwrap();
+    let _guard = runtime.enter();
+    count(engine_rx, counter.clone());
+    let target = serve(engine_tx);
+
+    let peers: Vec<_> = (0..PEERS).map(|_| join(target)).collect();
+    runtime.block_on(tokio::time::sleep(WARM_UP));
+    assert_eq!(
+        counter.load(Ordering::Relaxed),
+        PEERS as u64,
+        "not every peer joined"
+    );
+
+    let stop = Arc::new(AtomicBool::new(false));
+    let flooders: Vec<_> = peers
+        .into_iter()
+        .map(|socket| flood(socket, target, stop.clone()))
+        .collect();
+
+    runtime.block_on(tokio::time::sleep(WARM_UP));
+    let start_count = counter.load(Ordering::Relaxed);
+    let start = Instant::now();
+    runtime.block_on(tokio::time::sleep(MEASURE));
+    let handled = counter.load(Ordering::Relaxed) - start_count;
+    let elapsed = start.elapsed();
+
+    stop.store(true, Ordering::Relaxed);
+    for flooder in flooders {
+        flooder.join().unwrap();
+    }
+
+    let per_second = handled as f64 / elapsed.as_secs_f64();
+    println!("{name:<20} {per_second:>12.0} packets/s");
+}
+
+fn main() {
+    measure("spawn per datagram", |engine_tx| {
+        let socket = tokio::runtime::Handle::current()
+            .block_on(UdpSocket::bind("127.0.0.1:0"))
+            .unwrap();
+        let addr = socket.local_addr().unwrap();
+        tokio::spawn(spawn_per_datagram(socket, Arc::new(engine_tx)));
+        addr
+    });
+
+    measure("single owner", |engine_tx| {
+        let config = Config {
+            addr: "127.0.0.1:0".parse().unwrap(),
+            connection_quota: QUEUE_CAPACITY,
+            ..Default::default()
+        };
+        let mut server = tokio::runtime::Handle::current().block_on(Server::from_config(config));
+        server.engine_tx = Some(Arc::new(engine_tx));
+        let addr = server.local_addr().unwrap();
+        tokio::spawn(async move { server.run().await });
+        addr
+    });
+}
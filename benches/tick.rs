@@ -0,0 +1,45 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use lib::config::Config;
+use lib::engine::Engine;
+use lib::player::Player;
+use net::id::Id;
+
+/// The engine the server runs by default with `players` players moving diagonally, on top of its
+/// 65k entities.
+fn engine(players: u16) -> Engine {
+    let mut engine = Engine::from_config(&Config::default());
+    for i in 0..players {
+        let mut player = Player::new(Id(i));
+        player.velocity.max_x = 10.0;
+        player.velocity.max_y = 10.0;
+        player.acceleration.x = 10;
+        player.acceleration.y = -10;
+        engine.state.players.insert(player.id, player);
+    }
+    engine
+}
+
+fn tick(c: &mut Criterion) {
+    let mut group = c.benchmark_group("tick");
+    for players in [1, 100, 1000] {
+        let mut engine = engine(players);
+        group.bench_function(BenchmarkId::from_parameter(players), |b| {
+            b.iter(|| engine.step(1))
+        });
+    }
+    group.finish();
+}
+
+fn snapshot(c: &mut Criterion) {
+    let mut group = c.benchmark_group("snapshot");
+    for players in [1, 100, 1000] {
+        let engine = engine(players);
+        group.bench_function(BenchmarkId::from_parameter(players), |b| {
+            b.iter(|| engine.snapshot())
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, tick, snapshot);
+criterion_main!(benches);
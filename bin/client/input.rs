@@ -0,0 +1,125 @@
+use net::id::Id;
+use net::packet::movement::Movement;
+use std::time::{Duration, Instant};
+
+/// How long a key counts as held after it was last pressed, unless it is pressed again.
+pub const RELEASE_AFTER: Duration = Duration::from_millis(500);
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Key {
+    Up,
+    Down,
+    Left,
+    Right,
+}
+
+impl Key {
+    fn opposite(self) -> Key {
+        match self {
+            Key::Up => Key::Down,
+            Key::Down => Key::Up,
+            Key::Left => Key::Right,
+            Key::Right => Key::Left,
+        }
+    }
+}
+
+/// Which movement keys are held. Terminals only report key presses, never releases, so a key
+/// counts as held from its last press until `release_after` has passed. Key repeat keeps pressing
+/// a key that is really held down, which keeps it held.
+#[derive(Debug)]
+pub struct HeldKeys {
+    release_after: Duration,
+    /// When each key was last pressed, in the order of `Key`.
+    pressed: [Option<Instant>; 4],
+}
+
+impl HeldKeys {
+    pub fn new(release_after: Duration) -> Self {
+        Self {
+            release_after,
+            pressed: [None; 4],
+        }
+    }
+
+    /// Holds `key`, letting go of the opposite one so that changing direction does not have to
+    /// wait for it to be released.
+    pub fn press(&mut self, key: Key, now: Instant) {
+        self.pressed[key as usize] = Some(now);
+        self.pressed[key.opposite() as usize] = None;
+    }
+
+    pub fn release_all(&mut self) {
+        self.pressed = [None; 4];
+    }
+
+    pub fn is_held(&self, key: Key, now: Instant) -> bool {
+        self.pressed[key as usize]
+            .is_some_and(|pressed| now.saturating_duration_since(pressed) < self.release_after)
+    }
+
+    /// Every key's state for player `id`, which is what the server expects in each `Movement`.
+    pub fn movement(&self, id: Id, now: Instant) -> Movement {
+        Movement {
+            id,
+            up: self.is_held(Key::Up, now),
+            down: self.is_held(Key::Down, now),
+            left: self.is_held(Key::Left, now),
+            right: self.is_held(Key::Right, now),
+        }
+    }
+}
+
+impl Default for HeldKeys {
+    fn default() -> Self {
+        Self::new(RELEASE_AFTER)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    mod behavior {
+        use crate::input::{HeldKeys, Key};
+        use net::id::Id;
+        use std::time::{Duration, Instant};
+
+        #[test]
+        fn keys_are_held_together() {
+            let mut keys = HeldKeys::new(Duration::from_millis(500));
+            let now = Instant::now();
+            keys.press(Key::Up, now);
+            keys.press(Key::Right, now + Duration::from_millis(10));
+
+            let movement = keys.movement(Id(3), now + Duration::from_millis(20));
+            assert_eq!(movement.id, Id(3));
+            assert!(movement.up && movement.right);
+            assert!(!movement.down && !movement.left);
+        }
+
+        #[test]
+        fn keys_are_released_after_a_while() {
+            let mut keys = HeldKeys::new(Duration::from_millis(500));
+            let now = Instant::now();
+            keys.press(Key::Left, now);
+            assert!(keys.is_held(Key::Left, now + Duration::from_millis(499)));
+            assert!(!keys.is_held(Key::Left, now + Duration::from_millis(500)));
+
+            // Key repeat keeps a key held.
+            keys.press(Key::Left, now + Duration::from_millis(400));
+            assert!(keys.is_held(Key::Left, now + Duration::from_millis(800)));
+
+            keys.release_all();
+            assert!(!keys.is_held(Key::Left, now + Duration::from_millis(800)));
+        }
+
+        #[test]
+        fn pressing_a_key_releases_its_opposite() {
+            let mut keys = HeldKeys::default();
+            let now = Instant::now();
+            keys.press(Key::Up, now);
+            keys.press(Key::Down, now);
+            assert!(keys.is_held(Key::Down, now));
+            assert!(!keys.is_held(Key::Up, now));
+        }
+    }
+}
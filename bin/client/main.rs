@@ -0,0 +1,570 @@
+mod input;
+mod view;
+
+use console::Term;
+use input::{HeldKeys, Key, RELEASE_AFTER};
+use lib::config::INPUT_RATE_RANGE;
+use lib::logging;
+use net::frame::{Frame, FrameRef, MAX_DATAGRAM};
+use net::id::Id;
+use net::packet::disconnect::{Disconnect, Reason, DISCONNECT_REPEATS};
+use net::packet::join::Join;
+use net::packet::movement::Movement;
+use net::packet::ping::Ping;
+use net::packet::sync::Sync;
+use net::packet::welcome::Welcome;
+use net::packet::{Packet, PacketRef};
+use std::io;
+use std::io::BufRead;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::net::{lookup_host, UdpSocket};
+use tokio::sync::mpsc::{channel, Receiver, Sender};
+use tokio::task::JoinHandle;
+use tokio::time::{interval, timeout};
+use tracing::{debug, info, trace, warn};
+use view::{View, World, DEFAULT_SCALE};
+
+const USAGE: &str = "\
+Usage: client [OPTIONS]
+
+Options:
+  --server <HOST:PORT>    Server to join [default: 127.0.0.1:10001]
+  --bind <ADDR>           Local address to send from [default: any address, any port]
+  --nickname <NAME>       Name shown to other players, at most 16 bytes [default: player]
+  --mode <keys|lines>     Read single key presses from the terminal, or lines from stdin where
+                          every character is a key [default: keys]
+  --snapshot-rate <N>     Snapshots per second to ask for, 0 leaves it to the server [default: 0]
+  --scale <N>             World units per column of the view [default: 10]
+  --input-rate <N>        Times per second the held keys are sent, 1 to 1000 [default: 20]
+  --release-after <MS>    Milliseconds a key counts as held after it was last pressed, should be
+                          longer than the terminal takes to start repeating a key [default: 500]
+
+The view is only drawn when stdout is a terminal.
+
+Keys: w/a/s/d move, x lets go of all keys, +/- zoom, p asks for all players, r reconnects, q quits.";
+
+/// How long to wait for the server to answer a `Join`.
+const JOIN_TIMEOUT: Duration = Duration::from_secs(1);
+/// How often the round trip time is measured.
+const PING_INTERVAL: Duration = Duration::from_secs(1);
+/// How often the view is redrawn.
+const RENDER_INTERVAL: Duration = Duration::from_millis(33);
+/// How often the held keys are sent, so the server never has to guess them for long.
+const INPUT_RATE: f32 = 20.0;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum Mode {
+    /// Raw key presses from the terminal.
+    Keys,
+    /// Lines from stdin, so the client can be scripted.
+    Lines,
+}
+
+#[derive(Clone, Debug)]
+struct Options {
+    server: String,
+    bind: Option<SocketAddr>,
+    nickname: String,
+    mode: Mode,
+    snapshot_rate: u8,
+    scale: f32,
+    input_rate: f32,
+    release_after: Duration,
+}
+
+impl Options {
+    fn parse(args: impl IntoIterator<Item = String>) -> Result<Self, String> {
+        let mut options = Options {
+            server: "127.0.0.1:10001".to_string(),
+            bind: None,
+            nickname: "player".to_string(),
+            mode: Mode::Keys,
+            snapshot_rate: 0,
+            scale: DEFAULT_SCALE,
+            input_rate: INPUT_RATE,
+            release_after: RELEASE_AFTER,
+        };
+
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            let (flag, value) = match arg.split_once('=') {
+                Some((flag, value)) => (flag.to_string(), Some(value.to_string())),
+                None => (arg, None),
+            };
+            if flag == "--help" || flag == "-h" {
+                return Err(String::new());
+            }
+            let Some(value) = value.or_else(|| args.next()) else {
+                return Err(format!("No value given for {flag}"));
+            };
+
+            match flag.as_str() {
+                "--server" => options.server = value,
+                "--bind" => {
+                    let bind = value
+                        .parse()
+                        .map_err(|e| format!("Invalid --bind `{value}`: {e}"))?;
+                    options.bind = Some(bind);
+                }
+                "--nickname" => {
+                    if value.is_empty() {
+                        return Err("--nickname can not be empty".to_string());
+                    }
+                    options.nickname = value;
+                }
+                "--mode" => {
+                    options.mode = match value.as_str() {
+                        "keys" => Mode::Keys,
+                        "lines" => Mode::Lines,
+                        _ => return Err(format!("Invalid --mode `{value}`")),
+                    }
+                }
+                "--snapshot-rate" => {
+                    options.snapshot_rate = value
+                        .parse()
+                        .map_err(|e| format!("Invalid --snapshot-rate `{value}`: {e}"))?;
+                }
+                "--scale" => {
+                    options.scale = value
+                        .parse()
+                        .ok()
+                        .filter(|scale: &f32| scale.is_finite() && *scale > 0.0)
+                        .ok_or_else(|| format!("Invalid --scale `{value}`"))?;
+                }
+                "--input-rate" => {
+                    options.input_rate = value
+                        .parse()
+                        .ok()
+                        .filter(|rate| INPUT_RATE_RANGE.contains(rate))
+                        .ok_or_else(|| format!("Invalid --input-rate `{value}`"))?;
+                }
+                "--release-after" => {
+                    let millis: u64 = value
+                        .parse()
+                        .map_err(|e| format!("Invalid --release-after `{value}`: {e}"))?;
+                    options.release_after = Duration::from_millis(millis);
+                }
+                _ => return Err(format!("Unknown option {flag}")),
+            }
+        }
+
+        Ok(options)
+    }
+}
+
+/// What a key press asks the client to do.
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum Control {
+    Hold(Key),
+    ReleaseAll,
+    Sync,
+    Ping,
+    ZoomIn,
+    ZoomOut,
+    Reconnect,
+    Quit,
+}
+
+impl Control {
+    fn from_key(key: char) -> Self {
+        match key {
+            'w' => Control::Hold(Key::Up),
+            's' => Control::Hold(Key::Down),
+            'a' => Control::Hold(Key::Left),
+            'd' => Control::Hold(Key::Right),
+            'x' => Control::ReleaseAll,
+            'p' => Control::Sync,
+            '+' | '=' => Control::ZoomIn,
+            '-' => Control::ZoomOut,
+            'r' => Control::Reconnect,
+            'q' => Control::Quit,
+            _ => Control::Ping,
+        }
+    }
+}
+
+pub struct Client {
+    pub socket: Arc<UdpSocket>,
+    target: SocketAddr,
+    sequence_number: u32,
+    send_buf: Vec<u8>,
+    /// The player the server gave us, once joined.
+    id: Option<Id>,
+    /// From the last `Welcome`, so joining again after losing the connection gets the same player
+    /// back.
+    token: u64,
+}
+
+impl Client {
+    pub async fn connect(bind: SocketAddr, target: SocketAddr) -> io::Result<Self> {
+        let socket = UdpSocket::bind(bind).await?;
+        debug!(addr = ?socket.local_addr(), %target, "created socket");
+
+        Ok(Self {
+            socket: Arc::new(socket),
+            target,
+            sequence_number: 0,
+            send_buf: Vec::with_capacity(64),
+            id: None,
+            token: 0,
+        })
+    }
+
+    /// Returns the sequence number the frame went out with.
+    pub async fn send_frame(&mut self, mut frame: Frame) -> io::Result<u32> {
+        let syn = self.sequence_number;
+        frame.syn = syn;
+        self.sequence_number = self.sequence_number.wrapping_add(1);
+        self.send_buf.clear();
+        frame.encode(&mut self.send_buf);
+        self.socket.send_to(&self.send_buf, self.target).await?;
+        trace!(?frame, "sent frame");
+        Ok(syn)
+    }
+
+    pub async fn send(&mut self, packet: Packet) -> io::Result<u32> {
+        let mut frame = Frame::new();
+        frame.packet = Some(packet);
+        self.send_frame(frame).await
+    }
+
+    /// Asks the server for a player and waits for it to say which one, how often it ticks and how
+    /// often it will send snapshots, or why it will not.
+    pub async fn join(&mut self, snapshot_rate: u8, nickname: &str) -> io::Result<JoinReply> {
+        let mut join = Join::new(snapshot_rate, nickname);
+        join.token = self.token;
+        self.send(Packet::Join(join)).await?;
+
+        let mut buf = [0; MAX_DATAGRAM];
+        let wait = async {
+            loop {
+                let (n, origin) = self.socket.recv_from(&mut buf).await.ok()?;
+                if origin != self.target {
+                    continue;
+                }
+                match FrameRef::from_bytes(&buf[..n]).and_then(|frame| frame.packet) {
+                    Some(PacketRef::Welcome(welcome)) => return Some(JoinReply::Welcome(welcome)),
+                    Some(PacketRef::Disconnect(disconnect)) => {
+                        return Some(JoinReply::Refused(disconnect.to_owned()))
+                    }
+                    _ => {}
+                }
+            }
+        };
+        let reply = timeout(JOIN_TIMEOUT, wait)
+            .await
+            .ok()
+            .flatten()
+            .unwrap_or(JoinReply::NoAnswer);
+        if let JoinReply::Welcome(welcome) = &reply {
+            self.id = Some(welcome.id);
+            self.token = welcome.token;
+        }
+        Ok(reply)
+    }
+
+    /// Tells the server we are going, if we had joined, so it does not wait for us to time out.
+    pub async fn leave(&mut self) -> io::Result<()> {
+        if self.id.take().is_none() {
+            return Ok(());
+        }
+        for _ in 0..DISCONNECT_REPEATS {
+            self.send(Packet::Disconnect(Disconnect::new(Reason::Quit)))
+                .await?;
+        }
+        Ok(())
+    }
+}
+
+/// How a join request went.
+pub enum JoinReply {
+    Welcome(Welcome),
+    /// The server turned us away.
+    Refused(Disconnect),
+    NoAnswer,
+}
+
+/// Reads keys on a thread of its own, since both ways of reading them block. The channel closes
+/// when there is nothing left to read.
+fn read_keys(mode: Mode) -> Receiver<char> {
+    let (tx, rx) = channel(16);
+    std::thread::spawn(move || match mode {
+        Mode::Keys => {
+            let term = Term::stdout();
+            while let Ok(key) = term.read_char() {
+                if tx.blocking_send(key).is_err() {
+                    return;
+                }
+            }
+        }
+        Mode::Lines => send_lines(io::stdin().lock(), &tx),
+    });
+    rx
+}
+
+fn send_lines(input: impl BufRead, tx: &Sender<char>) {
+    for line in input.lines() {
+        let Ok(line) = line else {
+            return;
+        };
+        for key in line.chars().filter(|key| !key.is_whitespace()) {
+            if tx.blocking_send(key).is_err() {
+                return;
+            }
+        }
+    }
+}
+
+/// Decodes frames from the server into `world` until aborted, passing on why the server ended the
+/// session if it does.
+fn receive(
+    socket: Arc<UdpSocket>,
+    target: SocketAddr,
+    world: Arc<Mutex<World>>,
+    disconnects: Sender<Disconnect>,
+) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut buf = [0; MAX_DATAGRAM];
+        loop {
+            let (n, origin) = match socket.recv_from(&mut buf).await {
+                Ok(received) => received,
+                Err(e) => {
+                    debug!(error = %e, "receive failed");
+                    continue;
+                }
+            };
+            if origin != target {
+                continue;
+            }
+            match FrameRef::from_bytes(&buf[..n]) {
+                Some(FrameRef {
+                    packet: Some(PacketRef::Disconnect(disconnect)),
+                    ..
+                }) => {
+                    let _ = disconnects.try_send(disconnect.to_owned());
+                }
+                Some(frame) => world.lock().unwrap().handle_frame(frame, Instant::now()),
+                None => debug!(len = n, "dropping malformed frame"),
+            }
+        }
+    })
+}
+
+/// Stops the receive task however `play` returns.
+struct AbortOnDrop(JoinHandle<()>);
+
+impl Drop for AbortOnDrop {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
+}
+
+fn render(term: &Term, view: &View, world: &Mutex<World>) -> io::Result<()> {
+    let (rows, columns) = term.size();
+    let screen = view.render(
+        &world.lock().unwrap(),
+        Instant::now(),
+        columns as usize,
+        rows as usize,
+    );
+    term.move_cursor_to(0, 0)?;
+    term.write_str(&screen)
+}
+
+/// Joins and plays until asked to quit or reconnect. Returns whether to reconnect.
+async fn play(
+    client: &mut Client,
+    options: &Options,
+    keys: &mut Receiver<char>,
+) -> io::Result<bool> {
+    let mut world = match client
+        .join(options.snapshot_rate, &options.nickname)
+        .await?
+    {
+        JoinReply::Welcome(welcome) => {
+            info!(
+                id = welcome.id.0,
+                tick_rate = welcome.tick_rate,
+                snapshot_rate = welcome.snapshot_rate,
+                "joined"
+            );
+            Some(Arc::new(Mutex::new(World::new(&welcome))))
+        }
+        JoinReply::Refused(disconnect) => {
+            warn!("could not join, {disconnect}, press r to try again");
+            None
+        }
+        JoinReply::NoAnswer => {
+            warn!("server did not answer the join request, press r to try again");
+            None
+        }
+    };
+    let (disconnect_tx, mut disconnects) = channel(1);
+    let _receiver = world.as_ref().map(|world| {
+        AbortOnDrop(receive(
+            client.socket.clone(),
+            client.target,
+            world.clone(),
+            disconnect_tx,
+        ))
+    });
+
+    let term = Term::stdout();
+    let mut drawing = world.is_some() && term.is_term();
+    if drawing {
+        term.clear_screen()?;
+    }
+    let mut view = View::new(options.scale);
+    let mut held = HeldKeys::new(options.release_after);
+    // The last movement sent, so changes go out straight away rather than at the next input tick.
+    let mut sent: Option<Movement> = None;
+    let mut redraw = interval(RENDER_INTERVAL);
+    let mut ping = interval(PING_INTERVAL);
+    let mut input = interval(Duration::from_secs_f32(1.0 / options.input_rate));
+
+    loop {
+        tokio::select! {
+            key = keys.recv() => {
+                let Some(key) = key else {
+                    return Ok(false);
+                };
+                let now = Instant::now();
+                let packet = match Control::from_key(key) {
+                    Control::Quit => return Ok(false),
+                    Control::Reconnect => return Ok(true),
+                    Control::Hold(key) => {
+                        held.press(key, now);
+                        let Some(id) = client.id else {
+                            warn!("not joined yet, press r to try again");
+                            continue;
+                        };
+                        let movement = held.movement(id, now);
+                        if sent == Some(movement) {
+                            continue;
+                        }
+                        sent = Some(movement);
+                        Packet::Movement(movement)
+                    }
+                    Control::ReleaseAll => {
+                        held.release_all();
+                        continue;
+                    }
+                    Control::ZoomIn => {
+                        view.zoom_in();
+                        continue;
+                    }
+                    Control::ZoomOut => {
+                        view.zoom_out();
+                        continue;
+                    }
+                    Control::Sync => Packet::Sync(Sync),
+                    Control::Ping => Packet::Ping(Ping),
+                };
+                client.send(packet).await?;
+            }
+            // The whole input state goes out at a fixed rate, released keys included, since the
+            // server lets go of keys it has not heard about for a while.
+            _ = input.tick(), if client.id.is_some() => {
+                if let Some(id) = client.id {
+                    let movement = held.movement(id, Instant::now());
+                    sent = Some(movement);
+                    client.send(Packet::Movement(movement)).await?;
+                }
+            }
+            _ = ping.tick(), if world.is_some() => {
+                let syn = client.send(Packet::Ping(Ping)).await?;
+                if let Some(world) = &world {
+                    world.lock().unwrap().sent_ping(syn, Instant::now());
+                }
+            }
+            Some(disconnect) = disconnects.recv(), if client.id.is_some() => {
+                if drawing {
+                    term.clear_screen()?;
+                }
+                warn!("disconnected, {disconnect}, press r to reconnect or q to quit");
+                client.id = None;
+                world = None;
+                drawing = false;
+            }
+            _ = redraw.tick(), if drawing => {
+                if let Some(world) = &world {
+                    render(&term, &view, world)?;
+                }
+            }
+        }
+    }
+}
+
+async fn quit(client: &mut Client) {
+    if let Err(e) = client.leave().await {
+        debug!(error = %e, "could not say goodbye");
+    }
+}
+
+#[tokio::main]
+async fn main() {
+    // The terminal is for the player, logs go to stderr.
+    logging::init_with_writer(logging::DEFAULT_FILTER, std::io::stderr);
+
+    let options = match Options::parse(std::env::args().skip(1)) {
+        Ok(options) => options,
+        Err(e) => {
+            if !e.is_empty() {
+                eprintln!("{e}\n");
+            }
+            eprintln!("{USAGE}");
+            std::process::exit(2);
+        }
+    };
+
+    let target = match lookup_host(&options.server).await {
+        Ok(mut addrs) => match addrs.next() {
+            Some(target) => target,
+            None => {
+                eprintln!("No address found for {}", options.server);
+                std::process::exit(1);
+            }
+        },
+        Err(e) => {
+            eprintln!("Could not resolve {}: {e}", options.server);
+            std::process::exit(1);
+        }
+    };
+    let bind = options.bind.unwrap_or(match target {
+        SocketAddr::V4(_) => "0.0.0.0:0".parse().unwrap(),
+        SocketAddr::V6(_) => "[::]:0".parse().unwrap(),
+    });
+
+    let mut keys = read_keys(options.mode);
+    let mut token = 0;
+    loop {
+        let mut client = match Client::connect(bind, target).await {
+            Ok(client) => client,
+            Err(e) => {
+                eprintln!("Could not bind {bind}: {e}");
+                std::process::exit(1);
+            }
+        };
+
+        client.token = token;
+        let played = play(&mut client, &options, &mut keys).await;
+        // Reconnecting keeps the session, so the server holds on to the player until then.
+        token = client.token;
+        match played {
+            Ok(true) => info!("reconnecting"),
+            Ok(false) => return quit(&mut client).await,
+            Err(e) => {
+                warn!(error = %e, "connection failed, press r to reconnect or q to quit");
+                loop {
+                    match keys.recv().await.map(Control::from_key) {
+                        Some(Control::Reconnect) => break,
+                        Some(Control::Quit) | None => return quit(&mut client).await,
+                        Some(_) => {}
+                    }
+                }
+            }
+        }
+    }
+}
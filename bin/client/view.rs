@@ -0,0 +1,458 @@
+use net::frame::FrameRef;
+use net::id::Id;
+use net::interpolation::{Interpolator, DEFAULT_MAX_EXTRAPOLATION, DEFAULT_TELEPORT_DISTANCE};
+use net::packet::snapshot::{EntityState, SnapshotRef};
+use net::packet::welcome::Welcome;
+use net::packet::PacketRef;
+use net::position::Position;
+use std::collections::HashSet;
+use std::fmt::Write;
+use std::time::{Duration, Instant};
+
+/// World units one terminal column covers when the client starts.
+pub const DEFAULT_SCALE: f32 = 10.0;
+const MIN_SCALE: f32 = 0.5;
+const MAX_SCALE: f32 = 1000.0;
+/// Snapshot intervals everything is drawn behind the newest server time, so a single lost
+/// snapshot does not leave it extrapolating.
+const INTERPOLATION_SNAPSHOTS: f64 = 2.0;
+/// How far each new measurement moves the tick duration and snapshot interval estimates.
+const SMOOTHING: f64 = 0.1;
+/// Longer gaps between snapshots, in milliseconds, are a stall or lost snapshots rather than the
+/// pace of the server.
+const MAX_SAMPLE_GAP: f64 = 1000.0;
+
+/// What the client knows about the game, built from the frames the server sends.
+#[derive(Debug)]
+pub struct World {
+    pub id: Id,
+    /// Milliseconds of server time per tick. Measured from the snapshots, since the tick rate can
+    /// change while the game runs.
+    tick_duration: f64,
+    /// Milliseconds between snapshots arriving.
+    snapshot_interval: f64,
+    players: Interpolator,
+    entities: Interpolator,
+    latest: Option<Latest>,
+    player_count: usize,
+    entity_count: usize,
+    /// Sequence number and send time of the ping waiting for an answer.
+    ping: Option<(u32, Instant)>,
+    pub rtt: Option<Duration>,
+}
+
+/// The newest snapshot.
+#[derive(Copy, Clone, Debug)]
+struct Latest {
+    tick: u32,
+    /// Server time in milliseconds, counted by the client.
+    time: f64,
+    received: Instant,
+}
+
+impl World {
+    /// Starts from the rates in `welcome` and follows the server from there.
+    pub fn new(welcome: &Welcome) -> Self {
+        let snapshot_interval = 1000.0 / welcome.snapshot_rate.max(1.0) as f64;
+        let interpolator = || {
+            Interpolator::new(
+                INTERPOLATION_SNAPSHOTS * snapshot_interval,
+                DEFAULT_MAX_EXTRAPOLATION,
+                DEFAULT_TELEPORT_DISTANCE,
+            )
+        };
+        Self {
+            id: welcome.id,
+            tick_duration: 1000.0 / welcome.tick_rate.max(1.0) as f64,
+            snapshot_interval,
+            players: interpolator(),
+            entities: interpolator(),
+            latest: None,
+            player_count: 0,
+            entity_count: 0,
+            ping: None,
+            rtt: None,
+        }
+    }
+
+    pub fn tick(&self) -> Option<u32> {
+        self.latest.map(|latest| latest.tick)
+    }
+
+    /// Remembers when the ping with sequence number `syn` went out, replacing any older ping that
+    /// was not answered.
+    pub fn sent_ping(&mut self, syn: u32, now: Instant) {
+        self.ping = Some((syn, now));
+    }
+
+    pub fn handle_frame(&mut self, frame: FrameRef, now: Instant) {
+        match frame.packet {
+            Some(PacketRef::Snapshot(snapshot)) => self.apply_snapshot(snapshot, now),
+            // The server answers a ping right away, acknowledging it.
+            Some(PacketRef::Ping(_)) => {
+                if let Some((syn, sent)) = self.ping {
+                    if frame.ack == syn.wrapping_add(1) {
+                        self.rtt = Some(now.saturating_duration_since(sent));
+                        self.ping = None;
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Buffers every position in `snapshot`. Players and entities missing from the newest
+    /// snapshot are gone and forgotten, so a snapshot that arrives late only adds to those still
+    /// around.
+    pub fn apply_snapshot(&mut self, snapshot: SnapshotRef, now: Instant) {
+        let time = match self.latest {
+            Some(latest) if snapshot.tick <= latest.tick => {
+                let time = latest.time - (latest.tick - snapshot.tick) as f64 * self.tick_duration;
+                buffer(&mut self.players, snapshot.players(), time, false);
+                buffer(&mut self.entities, snapshot.entities(), time, false);
+                return;
+            }
+            Some(latest) => {
+                let ticks = (snapshot.tick - latest.tick) as f64;
+                let since = now.saturating_duration_since(latest.received).as_secs_f64() * 1000.0;
+                if since > 0.0 && since < MAX_SAMPLE_GAP {
+                    self.measure(since / ticks, since);
+                }
+                latest.time + ticks * self.tick_duration
+            }
+            None => snapshot.tick as f64 * self.tick_duration,
+        };
+
+        self.latest = Some(Latest {
+            tick: snapshot.tick,
+            time,
+            received: now,
+        });
+        self.player_count = snapshot.len();
+        self.entity_count = snapshot.entities().len();
+        buffer(&mut self.players, snapshot.players(), time, true);
+        buffer(&mut self.entities, snapshot.entities(), time, true);
+    }
+
+    /// Moves the estimates towards a tick duration and snapshot interval just seen, and the
+    /// interpolation delay with them.
+    fn measure(&mut self, tick_duration: f64, snapshot_interval: f64) {
+        self.tick_duration += SMOOTHING * (tick_duration - self.tick_duration);
+        self.snapshot_interval += SMOOTHING * (snapshot_interval - self.snapshot_interval);
+        let delay = INTERPOLATION_SNAPSHOTS * self.snapshot_interval;
+        self.players.set_delay(delay);
+        self.entities.set_delay(delay);
+    }
+
+    /// Estimated server time in milliseconds, moving on from the newest snapshot at wall clock
+    /// speed.
+    fn server_time(&self, now: Instant) -> Option<f64> {
+        let latest = self.latest?;
+        let since = now.saturating_duration_since(latest.received).as_secs_f64() * 1000.0;
+        Some(latest.time + since)
+    }
+
+    fn sample(&self, interpolator: &Interpolator, now: Instant) -> Vec<(Id, Position)> {
+        match self.server_time(now) {
+            Some(server_time) => interpolator.sample_all(server_time).collect(),
+            None => Vec::new(),
+        }
+    }
+}
+
+/// Buffers `states` at server `time`. From the newest snapshot, ids missing from `states` are
+/// forgotten, from an older one only ids still known are added to.
+fn buffer(
+    interpolator: &mut Interpolator,
+    states: impl Iterator<Item = EntityState>,
+    time: f64,
+    newest: bool,
+) {
+    let mut states: Vec<EntityState> = states.collect();
+    if newest {
+        let present: HashSet<Id> = states.iter().map(|state| state.id).collect();
+        let gone: Vec<Id> = interpolator
+            .ids()
+            .filter(|id| !present.contains(id))
+            .collect();
+        for id in gone {
+            interpolator.remove(id);
+        }
+    } else {
+        let known: HashSet<Id> = interpolator.ids().collect();
+        states.retain(|state| known.contains(&state.id));
+    }
+    for state in states {
+        interpolator.push(state.id, time, state.position);
+    }
+}
+
+/// Draws the world around the local player as text.
+#[derive(Copy, Clone, Debug)]
+pub struct View {
+    /// World units per column. Rows cover twice as much, since terminal cells are about twice as
+    /// tall as they are wide.
+    scale: f32,
+}
+
+impl View {
+    pub fn new(scale: f32) -> Self {
+        Self {
+            scale: scale.clamp(MIN_SCALE, MAX_SCALE),
+        }
+    }
+
+    pub fn zoom_in(&mut self) {
+        *self = Self::new(self.scale / 2.0);
+    }
+
+    pub fn zoom_out(&mut self) {
+        *self = Self::new(self.scale * 2.0);
+    }
+
+    /// `height` lines of `width` characters, the last of them a status line. The local player is
+    /// `@` in the middle, everybody else is `o` and entities are `.` where no player is.
+    pub fn render(&self, world: &World, now: Instant, width: usize, height: usize) -> String {
+        let rows = height.saturating_sub(1);
+        let players = world.sample(&world.players, now);
+        let center = players
+            .iter()
+            .find(|(id, _)| *id == world.id)
+            .map(|(_, position)| *position)
+            .unwrap_or_default();
+
+        let mut grid = vec![vec![' '; width]; rows];
+        // Row and column of `position`, if it is in view.
+        let place = |position: &Position| {
+            let column = ((position.x - center.x) / self.scale).round() + (width / 2) as f32;
+            let row = (rows / 2) as f32 - ((position.y - center.y) / (self.scale * 2.0)).round();
+            if column < 0.0 || row < 0.0 || column >= width as f32 || row >= rows as f32 {
+                return None;
+            }
+            Some((row as usize, column as usize))
+        };
+
+        for (_, position) in world.sample(&world.entities, now) {
+            if let Some((row, column)) = place(&position) {
+                grid[row][column] = '.';
+            }
+        }
+        for (id, position) in &players {
+            let Some((row, column)) = place(position) else {
+                continue;
+            };
+            let cell = &mut grid[row][column];
+            if *id == world.id {
+                *cell = '@';
+            } else if *cell != '@' {
+                *cell = 'o';
+            }
+        }
+
+        let mut out = String::with_capacity((width + 1) * height);
+        for row in grid {
+            out.extend(row);
+            out.push('\n');
+        }
+        out.push_str(&self.status(world, center, width));
+        out
+    }
+
+    fn status(&self, world: &World, center: Position, width: usize) -> String {
+        let mut status = format!("id {}", world.id.0);
+        match world.tick() {
+            Some(tick) => write!(status, "  tick {tick}").unwrap(),
+            None => status.push_str("  tick -"),
+        }
+        match world.rtt {
+            Some(rtt) => write!(status, "  rtt {:.1}ms", rtt.as_secs_f64() * 1000.0).unwrap(),
+            None => status.push_str("  rtt -"),
+        }
+        write!(
+            status,
+            "  players {}  entities {}  at ({:.0}, {:.0})  {} per column",
+            world.player_count, world.entity_count, center.x, center.y, self.scale
+        )
+        .unwrap();
+        status.chars().take(width).collect()
+    }
+}
+
+impl Default for View {
+    fn default() -> Self {
+        Self::new(DEFAULT_SCALE)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    mod render {
+        use crate::view::{View, World};
+        use net::frame::{Frame, FrameRef};
+        use net::id::Id;
+        use net::packet::ping::Ping;
+        use net::packet::snapshot::{EntityState, Snapshot, SnapshotRef};
+        use net::packet::welcome::Welcome;
+        use net::packet::Packet;
+        use net::position::Position;
+        use std::time::{Duration, Instant};
+
+        fn world() -> World {
+            World::new(&Welcome {
+                id: Id(1),
+                tick_rate: 20.0,
+                snapshot_rate: 20.0,
+                token: 1,
+            })
+        }
+
+        fn states(states: &[(u16, f32, f32)]) -> Vec<EntityState> {
+            states
+                .iter()
+                .map(|&(id, x, y)| EntityState {
+                    id: Id(id),
+                    position: Position::new(x, y),
+                })
+                .collect()
+        }
+
+        fn snapshot(tick: u32, players: &[(u16, f32, f32)]) -> Vec<u8> {
+            snapshot_with_entities(tick, players, &[])
+        }
+
+        fn snapshot_with_entities(
+            tick: u32,
+            players: &[(u16, f32, f32)],
+            entities: &[(u16, f32, f32)],
+        ) -> Vec<u8> {
+            let snapshot = Snapshot {
+                tick,
+                players: states(players),
+                entities: states(entities),
+            };
+            // Without the packet id in front.
+            snapshot.to_bytes()[1..].to_vec()
+        }
+
+        fn apply(world: &mut World, bytes: &[u8], now: Instant) {
+            world.apply_snapshot(SnapshotRef::from_bytes(bytes).unwrap(), now);
+        }
+
+        #[test]
+        fn draws_players_around_the_local_one() {
+            let mut world = world();
+            let now = Instant::now();
+            apply(
+                &mut world,
+                &snapshot(
+                    10,
+                    &[(1, 100.0, 100.0), (2, 120.0, 140.0), (3, 900.0, 900.0)],
+                ),
+                now,
+            );
+
+            let out = View::new(10.0).render(&world, now, 9, 6);
+            let lines: Vec<&str> = out.lines().collect();
+            assert_eq!(lines.len(), 6);
+            assert_eq!(lines[0], "      o  ");
+            assert_eq!(lines[2], "    @    ");
+            // The status line is cut to the width of the view.
+            assert_eq!(lines[5], "id 1  tic");
+            assert!(!out.contains("oo"));
+        }
+
+        #[test]
+        fn draws_entities_under_players() {
+            let mut world = world();
+            let now = Instant::now();
+            // Entity ids are not player ids, entity 1 is not the local player.
+            apply(
+                &mut world,
+                &snapshot_with_entities(
+                    10,
+                    &[(1, 100.0, 100.0), (2, 80.0, 100.0)],
+                    &[(1, 120.0, 140.0), (2, 100.0, 100.0), (3, 80.0, 100.0)],
+                ),
+                now,
+            );
+
+            let out = View::new(10.0).render(&world, now, 9, 6);
+            let lines: Vec<&str> = out.lines().collect();
+            assert_eq!(lines[0], "      .  ");
+            assert_eq!(lines[2], "  o @    ");
+            let status = View::new(10.0).render(&world, now, 80, 1);
+            assert!(status.contains("players 2  entities 3"), "{status}");
+        }
+
+        #[test]
+        fn timing_follows_the_snapshots() {
+            // The welcome said 20 ticks per second, the server now runs 100 and sends every tick.
+            let mut world = world();
+            let start = Instant::now();
+            let mut now = start;
+            for tick in 0..200 {
+                now = start + Duration::from_millis(10 * tick as u64);
+                apply(&mut world, &snapshot(tick, &[(1, tick as f32, 0.0)]), now);
+            }
+
+            assert!((world.tick_duration - 10.0).abs() < 0.1, "{world:?}");
+            assert!((world.players.delay() - 20.0).abs() < 0.5, "{world:?}");
+            // Two snapshots behind the newest, at tick 199.
+            let (_, position) = world.sample(&world.players, now)[0];
+            assert!((position.x - 197.0).abs() < 0.1, "{position:?}");
+        }
+
+        #[test]
+        fn forgets_players_missing_from_newer_snapshots() {
+            let mut world = world();
+            let now = Instant::now();
+            apply(
+                &mut world,
+                &snapshot(1, &[(1, 0.0, 0.0), (2, 10.0, 0.0)]),
+                now,
+            );
+            apply(&mut world, &snapshot(2, &[(1, 0.0, 0.0)]), now);
+            // A late snapshot does not bring player 2 back.
+            apply(
+                &mut world,
+                &snapshot(1, &[(1, 0.0, 0.0), (2, 10.0, 0.0)]),
+                now,
+            );
+
+            let out = View::new(10.0).render(&world, now, 5, 2);
+            assert_eq!(out.lines().next(), Some("  @  "));
+            assert_eq!(world.tick(), Some(2));
+        }
+
+        #[test]
+        fn status_shows_round_trip_time() {
+            let mut world = world();
+            let sent = Instant::now();
+            world.sent_ping(6, sent);
+
+            let mut pong = Frame::new();
+            pong.packet = Some(Packet::Ping(Ping));
+            pong.ack = 6;
+            let bytes = pong.to_bytes();
+            world.handle_frame(FrameRef::from_bytes(&bytes).unwrap(), sent);
+            assert_eq!(world.rtt, None);
+
+            pong.ack = 7;
+            let bytes = pong.to_bytes();
+            let answered = sent + Duration::from_micros(2500);
+            world.handle_frame(FrameRef::from_bytes(&bytes).unwrap(), answered);
+            assert_eq!(world.rtt, Some(Duration::from_micros(2500)));
+
+            let out = View::default().render(&world, Instant::now(), 80, 1);
+            assert!(out.starts_with("id 1  tick -  rtt 2.5ms  players 0"));
+        }
+
+        #[test]
+        fn zoom_is_clamped() {
+            let mut view = View::new(1.0);
+            view.zoom_in();
+            view.zoom_in();
+            assert_eq!(view.scale, 0.5);
+        }
+    }
+}
@@ -0,0 +1,343 @@
+use lib::rng::Rng;
+use net::frame::{Frame, FrameRef, MAX_DATAGRAM};
+use net::id::Id;
+use net::packet::disconnect::{Disconnect, Reason, DISCONNECT_REPEATS};
+use net::packet::join::Join;
+use net::packet::movement::Movement;
+use net::packet::ping::Ping;
+use net::packet::Packet;
+use net::packet::PacketRef;
+use std::collections::VecDeque;
+use std::io;
+use std::net::SocketAddr;
+use std::time::Duration;
+use tokio::net::UdpSocket;
+use tokio::time::{interval, sleep_until, timeout, Instant, MissedTickBehavior};
+use tracing::{debug, trace};
+
+/// How long a bot waits for its `Welcome` before trying again.
+const JOIN_TIMEOUT: Duration = Duration::from_secs(1);
+const JOIN_ATTEMPTS: u32 = 3;
+const PING_INTERVAL: Duration = Duration::from_secs(1);
+/// Pings older than this are counted as lost.
+const PING_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// How a bot moves.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Pattern {
+    /// Holds no keys, only keeps sending that.
+    Still,
+    /// Holds a new random combination of keys every step.
+    Random,
+    /// Turns through the eight directions, one step each.
+    Circle,
+    /// Holds the keys of one character per step and starts over at the end. `w`, `a`, `s` and
+    /// `d` are held alone, anything else holds nothing.
+    Script(Vec<char>),
+}
+
+impl Pattern {
+    pub fn parse(value: &str) -> Option<Pattern> {
+        match value {
+            "still" => Some(Pattern::Still),
+            "random" => Some(Pattern::Random),
+            "circle" => Some(Pattern::Circle),
+            _ => {
+                let script: Vec<char> = value.strip_prefix("script:")?.chars().collect();
+                (!script.is_empty()).then_some(Pattern::Script(script))
+            }
+        }
+    }
+
+    /// Keys held during `step`, as `(up, down, left, right)`.
+    pub fn keys(&self, step: usize, rng: &mut Rng) -> (bool, bool, bool, bool) {
+        match self {
+            Pattern::Still => (false, false, false, false),
+            Pattern::Random => {
+                let bits = rng.next_u64();
+                let up = bits & 1 != 0;
+                let left = bits & 2 != 0;
+                // Opposite keys cancel out, so pick one of each pair.
+                (up, !up && bits & 4 != 0, left, !left && bits & 8 != 0)
+            }
+            Pattern::Circle => {
+                const DIRECTIONS: [(bool, bool, bool, bool); 8] = [
+                    (true, false, false, false),
+                    (true, false, false, true),
+                    (false, false, false, true),
+                    (false, true, false, true),
+                    (false, true, false, false),
+                    (false, true, true, false),
+                    (false, false, true, false),
+                    (true, false, true, false),
+                ];
+                DIRECTIONS[step % DIRECTIONS.len()]
+            }
+            Pattern::Script(script) => match script[step % script.len()] {
+                'w' => (true, false, false, false),
+                's' => (false, true, false, false),
+                'a' => (false, false, true, false),
+                'd' => (false, false, false, true),
+                _ => (false, false, false, false),
+            },
+        }
+    }
+}
+
+/// How a bot behaves, the same for every bot in a run.
+#[derive(Clone, Debug)]
+pub struct BotOptions {
+    pub target: SocketAddr,
+    pub snapshot_rate: u8,
+    pub input_rate: f32,
+    pub step: Duration,
+    pub pattern: Pattern,
+    pub seed: u64,
+}
+
+/// What one bot saw during a run.
+#[derive(Clone, Debug, Default)]
+pub struct BotStats {
+    pub joined: bool,
+    /// Server tick rate from the `Welcome`.
+    pub tick_rate: f32,
+    pub frames_sent: u64,
+    pub bytes_sent: u64,
+    pub frames_received: u64,
+    pub bytes_received: u64,
+    /// Lowest and highest sequence number of the server's frames, for counting how many never
+    /// arrived.
+    pub server_syns: Option<(u32, u32)>,
+    pub snapshots: u64,
+    /// Tick and arrival time of the first and the newest snapshot.
+    pub first_snapshot: Option<(u32, Instant)>,
+    pub last_snapshot: Option<(u32, Instant)>,
+    /// Longest wait between two snapshots.
+    pub longest_gap: Duration,
+    pub pings_sent: u64,
+    pub rtts: Vec<Duration>,
+}
+
+impl BotStats {
+    /// Frames the server sent that never arrived, judging by gaps in their sequence numbers.
+    pub fn frames_lost(&self) -> u64 {
+        match self.server_syns {
+            Some((first, last)) => {
+                let expected = last.wrapping_sub(first) as u64 + 1;
+                expected.saturating_sub(self.frames_received)
+            }
+            None => 0,
+        }
+    }
+
+    fn received(&mut self, frame: &FrameRef, len: usize, now: Instant) {
+        self.frames_received += 1;
+        self.bytes_received += len as u64;
+        self.server_syns = Some(match self.server_syns {
+            Some((first, last)) => (first.min(frame.syn), last.max(frame.syn)),
+            None => (frame.syn, frame.syn),
+        });
+
+        if let Some(PacketRef::Snapshot(snapshot)) = frame.packet {
+            self.snapshots += 1;
+            if let Some((_, previous)) = self.last_snapshot {
+                self.longest_gap = self.longest_gap.max(now - previous);
+            }
+            self.first_snapshot.get_or_insert((snapshot.tick, now));
+            self.last_snapshot = Some((snapshot.tick, now));
+        }
+    }
+}
+
+struct Bot {
+    socket: UdpSocket,
+    syn: u32,
+    send_buf: Vec<u8>,
+    stats: BotStats,
+}
+
+impl Bot {
+    async fn send(&mut self, packet: Packet) -> io::Result<u32> {
+        let mut frame = Frame::new();
+        let syn = self.syn;
+        frame.syn = syn;
+        frame.packet = Some(packet);
+        self.syn = self.syn.wrapping_add(1);
+
+        self.send_buf.clear();
+        frame.encode(&mut self.send_buf);
+        self.socket.send(&self.send_buf).await?;
+        self.stats.frames_sent += 1;
+        self.stats.bytes_sent += self.send_buf.len() as u64;
+        Ok(syn)
+    }
+
+    async fn join(&mut self, snapshot_rate: u8, nickname: &str) -> io::Result<Option<Id>> {
+        let mut buf = [0; MAX_DATAGRAM];
+        for _ in 0..JOIN_ATTEMPTS {
+            self.send(Packet::Join(Join::new(snapshot_rate, nickname)))
+                .await?;
+            let deadline = Instant::now() + JOIN_TIMEOUT;
+            while let Ok(received) = timeout(
+                deadline.saturating_duration_since(Instant::now()),
+                self.socket.recv(&mut buf),
+            )
+            .await
+            {
+                let n = received?;
+                match FrameRef::from_bytes(&buf[..n]).and_then(|frame| frame.packet) {
+                    Some(PacketRef::Welcome(welcome)) => {
+                        self.stats.tick_rate = welcome.tick_rate;
+                        return Ok(Some(welcome.id));
+                    }
+                    // Like a full server, which is the ceiling a swarm is usually looking for.
+                    Some(PacketRef::Disconnect(disconnect)) => {
+                        debug!(reason = %disconnect.reason, "bot turned away");
+                        return Ok(None);
+                    }
+                    _ => {}
+                }
+            }
+        }
+        Ok(None)
+    }
+}
+
+/// Joins as bot number `index` and plays until `until`.
+pub async fn run(index: usize, options: BotOptions, until: Instant) -> io::Result<BotStats> {
+    let bind: SocketAddr = match options.target {
+        SocketAddr::V4(_) => "0.0.0.0:0".parse().unwrap(),
+        SocketAddr::V6(_) => "[::]:0".parse().unwrap(),
+    };
+    let socket = UdpSocket::bind(bind).await?;
+    socket.connect(options.target).await?;
+    let mut bot = Bot {
+        socket,
+        syn: 0,
+        send_buf: Vec::with_capacity(64),
+        stats: BotStats::default(),
+    };
+
+    let Some(id) = bot
+        .join(options.snapshot_rate, &format!("bot-{index}"))
+        .await?
+    else {
+        debug!(index, "bot could not join");
+        return Ok(bot.stats);
+    };
+    bot.stats.joined = true;
+    debug!(index, id = id.0, "bot joined");
+
+    let mut rng = Rng::new(options.seed.wrapping_add(index as u64));
+    let mut step = 0;
+    let mut keys = options.pattern.keys(step, &mut rng);
+    let mut pings: VecDeque<(u32, Instant)> = VecDeque::new();
+
+    let mut input = interval(Duration::from_secs_f32(1.0 / options.input_rate));
+    let mut steps = interval(options.step);
+    let mut ping = interval(PING_INTERVAL);
+    for timer in [&mut input, &mut steps, &mut ping] {
+        timer.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    }
+    let mut buf = [0; MAX_DATAGRAM];
+
+    loop {
+        tokio::select! {
+            _ = sleep_until(until) => break,
+            received = bot.socket.recv(&mut buf) => {
+                let Ok(n) = received else {
+                    continue;
+                };
+                let now = Instant::now();
+                let Some(frame) = FrameRef::from_bytes(&buf[..n]) else {
+                    continue;
+                };
+                bot.stats.received(&frame, n, now);
+                if let Some(PacketRef::Disconnect(disconnect)) = frame.packet {
+                    debug!(index, reason = %disconnect.reason, "bot disconnected");
+                    return Ok(bot.stats);
+                }
+                if let Some(PacketRef::Ping(_)) = frame.packet {
+                    let syn = frame.ack.wrapping_sub(1);
+                    if let Some(index) = pings.iter().position(|(ping, _)| *ping == syn) {
+                        let (_, sent) = pings.remove(index).unwrap();
+                        bot.stats.rtts.push(now - sent);
+                    }
+                }
+            }
+            _ = input.tick() => {
+                let (up, down, left, right) = keys;
+                let movement = Movement { id, up, down, left, right };
+                bot.send(Packet::Movement(movement)).await?;
+            }
+            _ = steps.tick() => {
+                step += 1;
+                keys = options.pattern.keys(step, &mut rng);
+                trace!(index, ?keys, "next step");
+            }
+            _ = ping.tick() => {
+                let now = Instant::now();
+                pings.retain(|(_, sent)| now - *sent < PING_TIMEOUT);
+                let syn = bot.send(Packet::Ping(Ping)).await?;
+                bot.stats.pings_sent += 1;
+                pings.push_back((syn, now));
+            }
+        }
+    }
+
+    // Left on purpose, so the server need not wait for the bot to time out.
+    for _ in 0..DISCONNECT_REPEATS {
+        bot.send(Packet::Disconnect(Disconnect::new(Reason::Quit)))
+            .await?;
+    }
+    Ok(bot.stats)
+}
+
+#[cfg(test)]
+mod tests {
+    mod behavior {
+        use crate::bot::{BotStats, Pattern};
+        use lib::rng::Rng;
+
+        #[test]
+        fn parses_patterns() {
+            assert_eq!(Pattern::parse("still"), Some(Pattern::Still));
+            assert_eq!(Pattern::parse("circle"), Some(Pattern::Circle));
+            assert_eq!(
+                Pattern::parse("script:wd"),
+                Some(Pattern::Script(vec!['w', 'd']))
+            );
+            assert_eq!(Pattern::parse("script:"), None);
+            assert_eq!(Pattern::parse("zigzag"), None);
+        }
+
+        #[test]
+        fn scripts_loop() {
+            let pattern = Pattern::parse("script:wx").unwrap();
+            let mut rng = Rng::new(1);
+            assert_eq!(pattern.keys(0, &mut rng), (true, false, false, false));
+            assert_eq!(pattern.keys(1, &mut rng), (false, false, false, false));
+            assert_eq!(pattern.keys(2, &mut rng), (true, false, false, false));
+        }
+
+        #[test]
+        fn random_keys_never_hold_opposites() {
+            let mut rng = Rng::new(7);
+            for step in 0..100 {
+                let (up, down, left, right) = Pattern::Random.keys(step, &mut rng);
+                assert!(!(up && down || left && right));
+            }
+        }
+
+        #[test]
+        fn counts_missing_server_frames() {
+            let stats = BotStats {
+                server_syns: Some((10, 19)),
+                frames_received: 7,
+                ..Default::default()
+            };
+            assert_eq!(stats.frames_lost(), 3);
+            assert_eq!(BotStats::default().frames_lost(), 0);
+        }
+    }
+}
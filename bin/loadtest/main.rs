@@ -0,0 +1,201 @@
+mod bot;
+mod report;
+
+use bot::{BotOptions, Pattern};
+use lib::config::INPUT_RATE_RANGE;
+use lib::logging;
+use report::{Report, ServerMetrics};
+use std::net::SocketAddr;
+use std::time::Duration;
+use tokio::net::lookup_host;
+use tokio::task::JoinSet;
+use tokio::time::{sleep_until, Instant};
+use tracing::{info, warn};
+
+const USAGE: &str = "\
+Usage: loadtest [OPTIONS]
+
+Joins a server with many bots from one process and reports how it went.
+
+Options:
+  --server <HOST:PORT>    Server to load [default: 127.0.0.1:10001]
+  --clients <N>           Bots to run [default: 10]
+  --duration <SECONDS>    How long to run once every bot has started [default: 30]
+  --ramp <SECONDS>        Spreads the bots joining over this long [default: 1]
+  --input-rate <N>        Inputs per second each bot sends, 1 to 1000 [default: 20]
+  --pattern <PATTERN>     still, random, circle, or script:<keys> where each of w/a/s/d is held
+                          for one step and anything else holds nothing [default: random]
+  --step <MS>             Milliseconds each step of the pattern lasts [default: 250]
+  --snapshot-rate <N>     Snapshots per second to ask for, 0 leaves it to the server [default: 0]
+  --seed <N>              Seeds the random pattern [default: 0]
+  --metrics <HOST:PORT>   The server's metrics address, to report how its ticks held up";
+
+#[derive(Clone, Debug)]
+struct Options {
+    server: String,
+    clients: usize,
+    duration: Duration,
+    ramp: Duration,
+    input_rate: f32,
+    pattern: Pattern,
+    step: Duration,
+    snapshot_rate: u8,
+    seed: u64,
+    metrics: Option<String>,
+}
+
+impl Options {
+    fn parse(args: impl IntoIterator<Item = String>) -> Result<Self, String> {
+        let mut options = Options {
+            server: "127.0.0.1:10001".to_string(),
+            clients: 10,
+            duration: Duration::from_secs(30),
+            ramp: Duration::from_secs(1),
+            input_rate: 20.0,
+            pattern: Pattern::Random,
+            step: Duration::from_millis(250),
+            snapshot_rate: 0,
+            seed: 0,
+            metrics: None,
+        };
+
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            let (flag, value) = match arg.split_once('=') {
+                Some((flag, value)) => (flag.to_string(), Some(value.to_string())),
+                None => (arg, None),
+            };
+            if flag == "--help" || flag == "-h" {
+                return Err(String::new());
+            }
+            let Some(value) = value.or_else(|| args.next()) else {
+                return Err(format!("No value given for {flag}"));
+            };
+            let invalid = || format!("Invalid {flag} `{value}`");
+
+            match flag.as_str() {
+                "--server" => options.server = value,
+                "--clients" => {
+                    options.clients = value.parse().map_err(|_| invalid())?;
+                    if options.clients == 0 {
+                        return Err(invalid());
+                    }
+                }
+                "--duration" => options.duration = seconds(&value).ok_or_else(invalid)?,
+                "--ramp" => options.ramp = seconds(&value).ok_or_else(invalid)?,
+                "--input-rate" => {
+                    options.input_rate = value
+                        .parse()
+                        .ok()
+                        .filter(|rate| INPUT_RATE_RANGE.contains(rate))
+                        .ok_or_else(invalid)?;
+                }
+                "--pattern" => options.pattern = Pattern::parse(&value).ok_or_else(invalid)?,
+                "--step" => {
+                    let millis: u64 = value.parse().map_err(|_| invalid())?;
+                    if millis == 0 {
+                        return Err(invalid());
+                    }
+                    options.step = Duration::from_millis(millis);
+                }
+                "--snapshot-rate" => {
+                    options.snapshot_rate = value.parse().map_err(|_| invalid())?
+                }
+                "--seed" => options.seed = value.parse().map_err(|_| invalid())?,
+                "--metrics" => options.metrics = Some(value),
+                _ => return Err(format!("Unknown option {flag}")),
+            }
+        }
+
+        Ok(options)
+    }
+}
+
+fn seconds(value: &str) -> Option<Duration> {
+    let seconds: f64 = value.parse().ok()?;
+    (seconds.is_finite() && seconds >= 0.0).then(|| Duration::from_secs_f64(seconds))
+}
+
+async fn resolve(host: &str) -> SocketAddr {
+    match lookup_host(host).await.map(|mut addrs| addrs.next()) {
+        Ok(Some(addr)) => addr,
+        Ok(None) => {
+            eprintln!("No address found for {host}");
+            std::process::exit(1);
+        }
+        Err(e) => {
+            eprintln!("Could not resolve {host}: {e}");
+            std::process::exit(1);
+        }
+    }
+}
+
+async fn scrape(addr: Option<SocketAddr>) -> Option<ServerMetrics> {
+    let addr = addr?;
+    match ServerMetrics::scrape(addr).await {
+        Ok(metrics) => Some(metrics),
+        Err(e) => {
+            warn!(%addr, error = %e, "could not read server metrics");
+            None
+        }
+    }
+}
+
+#[tokio::main]
+async fn main() {
+    // The report goes to stdout, logs to stderr.
+    logging::init_with_writer("warn", std::io::stderr);
+
+    let options = match Options::parse(std::env::args().skip(1)) {
+        Ok(options) => options,
+        Err(e) => {
+            if !e.is_empty() {
+                eprintln!("{e}\n");
+            }
+            eprintln!("{USAGE}");
+            std::process::exit(2);
+        }
+    };
+
+    let target = resolve(&options.server).await;
+    let metrics_addr = match &options.metrics {
+        Some(metrics) => Some(resolve(metrics).await),
+        None => None,
+    };
+    let bot_options = BotOptions {
+        target,
+        snapshot_rate: options.snapshot_rate,
+        input_rate: options.input_rate,
+        step: options.step,
+        pattern: options.pattern.clone(),
+        seed: options.seed,
+    };
+
+    let before = scrape(metrics_addr).await;
+    let start = Instant::now();
+    let until = start + options.ramp + options.duration;
+    info!(clients = options.clients, %target, "starting bots");
+
+    let mut bots = JoinSet::new();
+    for index in 0..options.clients {
+        let ramp = options.ramp.mul_f64(index as f64 / options.clients as f64);
+        sleep_until(start + ramp).await;
+        bots.spawn(bot::run(index, bot_options.clone(), until));
+    }
+
+    let mut stats = Vec::with_capacity(options.clients);
+    while let Some(result) = bots.join_next().await {
+        match result {
+            Ok(Ok(bot)) => stats.push(bot),
+            Ok(Err(e)) => warn!(error = %e, "bot failed"),
+            Err(e) => warn!(error = %e, "bot panicked"),
+        }
+    }
+
+    let mut report = Report::new(&stats, start.elapsed());
+    report.bots = options.clients;
+    if let (Some(before), Some(after)) = (before, scrape(metrics_addr).await) {
+        report.server = Some(before.since(&after));
+    }
+    print!("{report}");
+}
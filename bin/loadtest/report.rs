@@ -0,0 +1,281 @@
+use crate::bot::BotStats;
+use std::fmt::{Display, Formatter};
+use std::io;
+use std::net::SocketAddr;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+/// Counters read from the server's `/metrics`, to tell how the server coped rather than how the
+/// bots saw it.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct ServerMetrics {
+    pub tick_seconds: f64,
+    pub ticks: f64,
+    pub tick_overruns: f64,
+    pub overload_dropped: f64,
+    pub decode_errors: f64,
+}
+
+impl ServerMetrics {
+    pub async fn scrape(addr: SocketAddr) -> io::Result<ServerMetrics> {
+        let mut stream = TcpStream::connect(addr).await?;
+        stream
+            .write_all(b"GET /metrics HTTP/1.1\r\nHost: loadtest\r\nConnection: close\r\n\r\n")
+            .await?;
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await?;
+        Ok(Self::parse(&response))
+    }
+
+    pub fn parse(text: &str) -> ServerMetrics {
+        ServerMetrics {
+            tick_seconds: sample(text, "game_tick_duration_seconds_sum"),
+            ticks: sample(text, "game_tick_duration_seconds_count"),
+            tick_overruns: sample(text, "game_tick_overruns_total"),
+            overload_dropped: sample(text, "game_overload_dropped_total"),
+            decode_errors: sample(text, "game_decode_errors_total"),
+        }
+    }
+
+    /// What changed between `self` and the `later` scrape.
+    pub fn since(&self, later: &ServerMetrics) -> ServerMetrics {
+        ServerMetrics {
+            tick_seconds: later.tick_seconds - self.tick_seconds,
+            ticks: later.ticks - self.ticks,
+            tick_overruns: later.tick_overruns - self.tick_overruns,
+            overload_dropped: later.overload_dropped - self.overload_dropped,
+            decode_errors: later.decode_errors - self.decode_errors,
+        }
+    }
+}
+
+/// Sum of every sample of metric `name`, whatever its labels.
+fn sample(text: &str, name: &str) -> f64 {
+    text.lines()
+        .filter(|line| !line.starts_with('#'))
+        .filter_map(|line| {
+            let (metric, value) = line.rsplit_once(' ')?;
+            let metric = metric.split('{').next()?;
+            (metric == name).then(|| value.parse::<f64>().ok())?
+        })
+        .sum()
+}
+
+/// Everything the bots saw, added up.
+#[derive(Clone, Debug, Default)]
+pub struct Report {
+    pub bots: usize,
+    pub joined: usize,
+    pub duration: Duration,
+    pub frames_sent: u64,
+    pub bytes_sent: u64,
+    pub frames_received: u64,
+    pub bytes_received: u64,
+    pub frames_lost: u64,
+    pub pings_sent: u64,
+    pub rtts: Vec<Duration>,
+    pub snapshots: u64,
+    pub longest_gap: Duration,
+    /// Ticks per second the server managed, going by snapshot ticks, and what it promised.
+    pub tick_rate: Option<(f64, f32)>,
+    pub server: Option<ServerMetrics>,
+}
+
+impl Report {
+    pub fn new(stats: &[BotStats], duration: Duration) -> Report {
+        let mut report = Report {
+            bots: stats.len(),
+            duration,
+            ..Default::default()
+        };
+        let mut tick_rates = Vec::new();
+
+        for bot in stats {
+            report.joined += bot.joined as usize;
+            report.frames_sent += bot.frames_sent;
+            report.bytes_sent += bot.bytes_sent;
+            report.frames_received += bot.frames_received;
+            report.bytes_received += bot.bytes_received;
+            report.frames_lost += bot.frames_lost();
+            report.pings_sent += bot.pings_sent;
+            report.rtts.extend(&bot.rtts);
+            report.snapshots += bot.snapshots;
+            report.longest_gap = report.longest_gap.max(bot.longest_gap);
+
+            if let (Some((first, first_at)), Some((last, last_at))) =
+                (bot.first_snapshot, bot.last_snapshot)
+            {
+                let elapsed = (last_at - first_at).as_secs_f64();
+                if elapsed > 0.0 {
+                    tick_rates.push((last.wrapping_sub(first) as f64 / elapsed, bot.tick_rate));
+                }
+            }
+        }
+        report.rtts.sort_unstable();
+
+        if !tick_rates.is_empty() {
+            let observed = tick_rates.iter().map(|(rate, _)| rate).sum::<f64>();
+            report.tick_rate = Some((observed / tick_rates.len() as f64, tick_rates[0].1));
+        }
+        report
+    }
+
+    /// Nearest rank percentile of the round trip times.
+    pub fn rtt(&self, p: f64) -> Option<Duration> {
+        let rank = (p * self.rtts.len() as f64).ceil() as usize;
+        self.rtts
+            .get(rank.clamp(1, self.rtts.len().max(1)) - 1)
+            .copied()
+    }
+
+    fn per_bot_kbits(&self, bytes: u64) -> f64 {
+        let seconds = self.duration.as_secs_f64().max(f64::EPSILON);
+        bytes as f64 * 8.0 / 1000.0 / seconds / self.joined.max(1) as f64
+    }
+}
+
+fn percent(part: u64, whole: u64) -> f64 {
+    if whole == 0 {
+        return 0.0;
+    }
+    part as f64 * 100.0 / whole as f64
+}
+
+fn millis(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+impl Display for Report {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "bots          {} of {} joined", self.joined, self.bots)?;
+        writeln!(f, "duration      {:.1}s", self.duration.as_secs_f64())?;
+        writeln!(
+            f,
+            "sent          {} frames, {:.1} kB, {:.1} kbit/s per bot",
+            self.frames_sent,
+            self.bytes_sent as f64 / 1000.0,
+            self.per_bot_kbits(self.bytes_sent)
+        )?;
+        writeln!(
+            f,
+            "received      {} frames, {:.1} kB, {:.1} kbit/s per bot",
+            self.frames_received,
+            self.bytes_received as f64 / 1000.0,
+            self.per_bot_kbits(self.bytes_received)
+        )?;
+        let expected = self.frames_received + self.frames_lost;
+        writeln!(
+            f,
+            "loss          {} of {} server frames ({:.2}%)",
+            self.frames_lost,
+            expected,
+            percent(self.frames_lost, expected)
+        )?;
+
+        let answered = self.rtts.len() as u64;
+        match (self.rtt(0.5), self.rtt(0.99), self.rtts.last()) {
+            (Some(p50), Some(p99), Some(&max)) => writeln!(
+                f,
+                "rtt           p50 {:.2}ms, p99 {:.2}ms, max {:.2}ms, {:.2}% of {} pings unanswered",
+                millis(p50),
+                millis(p99),
+                millis(max),
+                percent(self.pings_sent.saturating_sub(answered), self.pings_sent),
+                self.pings_sent
+            )?,
+            _ => writeln!(f, "rtt           no pings answered")?,
+        }
+
+        let seconds = self.duration.as_secs_f64().max(f64::EPSILON);
+        writeln!(
+            f,
+            "snapshots     {:.1}/s per bot, longest gap {:.0}ms",
+            self.snapshots as f64 / seconds / self.joined.max(1) as f64,
+            millis(self.longest_gap)
+        )?;
+        match self.tick_rate {
+            Some((observed, promised)) => writeln!(
+                f,
+                "server ticks  {observed:.2}/s of {promised}/s ({:.1}%)",
+                observed * 100.0 / promised.max(f32::EPSILON) as f64
+            )?,
+            None => writeln!(f, "server ticks  no snapshots")?,
+        }
+
+        if let Some(server) = &self.server {
+            let average = if server.ticks > 0.0 {
+                server.tick_seconds / server.ticks * 1000.0
+            } else {
+                0.0
+            };
+            writeln!(
+                f,
+                "server        {:.3}ms per update, {} over budget, {} packets dropped, {} decode errors",
+                average, server.tick_overruns, server.overload_dropped, server.decode_errors
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    mod behavior {
+        use crate::bot::BotStats;
+        use crate::report::{Report, ServerMetrics};
+        use std::time::Duration;
+        use tokio::time::Instant;
+
+        #[test]
+        fn adds_up_bots() {
+            let start = Instant::now();
+            let bot = |rtt: u64| BotStats {
+                joined: true,
+                tick_rate: 20.0,
+                frames_sent: 100,
+                frames_received: 98,
+                server_syns: Some((0, 99)),
+                pings_sent: 2,
+                rtts: vec![Duration::from_millis(rtt)],
+                first_snapshot: Some((0, start)),
+                last_snapshot: Some((40, start + Duration::from_secs(2))),
+                ..Default::default()
+            };
+            let stats = vec![bot(1), bot(3), BotStats::default()];
+
+            let report = Report::new(&stats, Duration::from_secs(10));
+            assert_eq!(report.bots, 3);
+            assert_eq!(report.joined, 2);
+            assert_eq!(report.frames_sent, 200);
+            assert_eq!(report.frames_lost, 4);
+            assert_eq!(report.rtt(0.5), Some(Duration::from_millis(1)));
+            assert_eq!(report.rtt(0.99), Some(Duration::from_millis(3)));
+            assert_eq!(report.tick_rate, Some((20.0, 20.0)));
+            assert!(report.to_string().contains("50.00% of 4 pings unanswered"));
+        }
+
+        #[test]
+        fn reads_metrics() {
+            let before = ServerMetrics::parse(
+                "# TYPE game_tick_overruns_total counter\n\
+                 game_tick_overruns_total 2\n\
+                 game_tick_duration_seconds_sum 0.5\n\
+                 game_tick_duration_seconds_count 100\n\
+                 game_overload_dropped_total{policy=\"drop_oldest\"} 1\n\
+                 game_overload_dropped_total{policy=\"drop_newest\"} 2\n",
+            );
+            assert_eq!(before.overload_dropped, 3.0);
+
+            let after = ServerMetrics {
+                tick_overruns: 5.0,
+                ticks: 300.0,
+                ..before
+            };
+            let change = before.since(&after);
+            assert_eq!(change.tick_overruns, 3.0);
+            assert_eq!(change.ticks, 200.0);
+            assert_eq!(change.overload_dropped, 0.0);
+        }
+    }
+}
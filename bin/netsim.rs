@@ -0,0 +1,145 @@
+use lib::logging;
+use lib::netsim::{NetsimConfig, Proxy, KEYS};
+use std::net::SocketAddr;
+use tokio::net::lookup_host;
+use tracing::info;
+
+const USAGE: &str = "\
+Usage: netsim [OPTIONS]
+
+Forwards UDP from clients to a server, making the network worse on the way. Point clients at
+--listen instead of the server.
+
+Options:
+  --listen <ADDR>         Where clients send to [default: 127.0.0.1:10002]
+  --server <HOST:PORT>    Server to forward to [default: 127.0.0.1:10001]
+  --seed <N>              Seeds loss, jitter, duplication and reordering [default: 0]
+
+Conditions, for both directions, or prefixed with up- (client to server) or down- (server to
+client) for one, e.g. --delay 50 --down-loss 0.05:
+  --delay <MS>            Added to every datagram
+  --jitter <MS>           Up to this much more delay, picked for each datagram
+  --loss <P>              Chance of a datagram being dropped, from 0 to 1
+  --duplicate <P>         Chance of a datagram arriving twice
+  --reorder <P>           Chance of a datagram being held back by --reorder-delay
+  --reorder-delay <MS>    [default: 100]
+  --bandwidth <KBIT/S>    Datagrams queue up beyond this, 0 is unlimited [default: 0]";
+
+#[derive(Clone, Debug)]
+struct Options {
+    listen: SocketAddr,
+    server: String,
+    netsim: NetsimConfig,
+}
+
+impl Options {
+    fn parse(args: impl IntoIterator<Item = String>) -> Result<Self, String> {
+        let mut options = Options {
+            listen: "127.0.0.1:10002".parse().unwrap(),
+            server: "127.0.0.1:10001".to_string(),
+            netsim: NetsimConfig::default(),
+        };
+
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            let (flag, value) = match arg.split_once('=') {
+                Some((flag, value)) => (flag.to_string(), Some(value.to_string())),
+                None => (arg, None),
+            };
+            if flag == "--help" || flag == "-h" {
+                return Err(String::new());
+            }
+            let Some(value) = value.or_else(|| args.next()) else {
+                return Err(format!("No value given for {flag}"));
+            };
+
+            let Some(name) = flag.strip_prefix("--") else {
+                return Err(format!("Unknown option {flag}"));
+            };
+            let name = name.replace('-', "_");
+            match name.as_str() {
+                "listen" => {
+                    options.listen = value
+                        .parse()
+                        .map_err(|e| format!("Invalid --listen `{value}`: {e}"))?;
+                }
+                "server" => options.server = value,
+                "seed" => {
+                    options.netsim.seed = value
+                        .parse()
+                        .map_err(|e| format!("Invalid --seed `{value}`: {e}"))?;
+                }
+                _ => {
+                    let (up, down, key) =
+                        match (name.strip_prefix("up_"), name.strip_prefix("down_")) {
+                            (Some(key), _) => (true, false, key),
+                            (_, Some(key)) => (false, true, key),
+                            _ => (true, true, name.as_str()),
+                        };
+                    if !KEYS.contains(&key) {
+                        return Err(format!("Unknown option {flag}"));
+                    }
+                    if up {
+                        options.netsim.up.set(key, &value)?;
+                    }
+                    if down {
+                        options.netsim.down.set(key, &value)?;
+                    }
+                }
+            }
+        }
+
+        Ok(options)
+    }
+}
+
+#[tokio::main]
+async fn main() {
+    logging::init(logging::DEFAULT_FILTER);
+
+    let options = match Options::parse(std::env::args().skip(1)) {
+        Ok(options) => options,
+        Err(e) => {
+            if !e.is_empty() {
+                eprintln!("{e}\n");
+            }
+            eprintln!("{USAGE}");
+            std::process::exit(2);
+        }
+    };
+
+    let server = match lookup_host(&options.server)
+        .await
+        .map(|mut addrs| addrs.next())
+    {
+        Ok(Some(server)) => server,
+        Ok(None) => {
+            eprintln!("No address found for {}", options.server);
+            std::process::exit(1);
+        }
+        Err(e) => {
+            eprintln!("Could not resolve {}: {e}", options.server);
+            std::process::exit(1);
+        }
+    };
+
+    let proxy = match Proxy::bind(options.listen, server, options.netsim).await {
+        Ok(proxy) => proxy,
+        Err(e) => {
+            eprintln!("Could not bind {}: {e}", options.listen);
+            std::process::exit(1);
+        }
+    };
+    info!(
+        listen = %options.listen,
+        %server,
+        up = ?options.netsim.up,
+        down = ?options.netsim.down,
+        "forwarding"
+    );
+
+    if let Err(e) = proxy.run().await {
+        eprintln!("netsim stopped: {e}");
+        std::process::exit(1);
+    }
+}
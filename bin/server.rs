@@ -1,118 +1,154 @@
+use lib::admin::AdminCommand;
 use lib::config::Config;
 use lib::engine::Engine;
-use net::frame::Frame;
-use net::packet::Packet;
-use std::collections::HashMap;
-use std::net::SocketAddr;
+use lib::logging::{self, LogHandle};
+use lib::metrics::{self, Metrics};
+use lib::reload::{self, watch_config};
+use lib::server::Server;
+use net::packet::snapshot::Snapshot;
+use std::io::Write;
+use std::sync::atomic::Ordering;
 use std::sync::Arc;
-use tokio::net::UdpSocket;
+use tokio::io::{stdin, AsyncBufReadExt, BufReader};
+use tokio::net::TcpListener;
 use tokio::sync::mpsc::{channel, Sender};
-use tokio::sync::Mutex;
+use tokio::sync::watch;
+use tokio::time::timeout;
+use tracing::{error, info, warn};
 
-#[derive(Debug)]
-pub struct Connection {
-    origin: SocketAddr,
-    sequence_number: u32,
-    acknowledgement_number: u32,
-    pub engine_tx: Option<Arc<Sender<Packet>>>,
-}
-
-impl Connection {
-    pub fn new(origin: SocketAddr) -> Self {
-        Self {
-            origin,
-            sequence_number: 0,
-            acknowledgement_number: 0,
-            engine_tx: None,
+async fn read_admin_commands(
+    admin_tx: Sender<AdminCommand>,
+    reload_tx: Sender<()>,
+    log: LogHandle,
+) {
+    let mut lines = BufReader::new(stdin()).lines();
+    while let Ok(Some(line)) = lines.next_line().await {
+        match AdminCommand::parse(&line) {
+            Ok(AdminCommand::SetLogFilter(filter)) => match log.set_filter(&filter) {
+                Ok(()) => info!(%filter, "log filter changed"),
+                Err(e) => warn!(%filter, error = %e, "invalid log filter"),
+            },
+            Ok(AdminCommand::Reload) => {
+                let _ = reload_tx.try_send(());
+            }
+            Ok(command) => {
+                if admin_tx.send(command).await.is_err() {
+                    return;
+                }
+            }
+            Err(e) => warn!("{e}"),
         }
     }
+}
 
-    pub async fn handle_frame(&mut self, frame: Frame) {
-        let Some(tx) = &mut self.engine_tx else {
-            return;
-        };
-
-        let syn = frame.syn;
-        if syn < self.acknowledgement_number {
-            return;
-        }
-
-        self.acknowledgement_number = syn + 1;
-
-        if let Some(packet) = frame.packet {
-            tx.send(packet).await.unwrap();
+/// Resolves on Ctrl-C, or on SIGTERM where there is such a thing.
+async fn shutdown_signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+        match signal(SignalKind::terminate()) {
+            Ok(mut terminate) => {
+                tokio::select! {
+                    _ = tokio::signal::ctrl_c() => {}
+                    _ = terminate.recv() => {}
+                }
+                return;
+            }
+            Err(e) => warn!(error = %e, "cannot listen for SIGTERM"),
         }
     }
+    if let Err(e) = tokio::signal::ctrl_c().await {
+        warn!(error = %e, "cannot listen for Ctrl-C");
+        std::future::pending::<()>().await;
+    }
 }
 
-pub struct Server {
-    socket: UdpSocket,
-    pub engine_tx: Option<Arc<Sender<Packet>>>,
+/// Logs the counters a scrape would have seen last, since nothing will be serving them anymore.
+fn log_final_metrics(metrics: &Metrics) {
+    info!(
+        ticks = metrics.tick_duration.count(),
+        tick_overruns = metrics.tick_overruns.load(Ordering::Relaxed),
+        decode_errors = metrics.decode_errors.load(Ordering::Relaxed),
+        overload_dropped = metrics.overload.dropped(),
+        "final metrics"
+    );
 }
 
-impl Server {
-    pub async fn new() -> Self {
-        Self::from_config(Config::default()).await
-    }
+#[tokio::main()]
+async fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let config = match Config::load(args.clone()) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("Config error: {e}");
+            std::process::exit(2);
+        }
+    };
+    let log = logging::init(&config.log_filter);
+    let (engine_tx, server_rx) = channel(config.engine_queue_capacity);
+    let (admin_tx, admin_rx) = channel(5);
+    let (reload_tx, reload_rx) = channel(1);
+    let (snapshot_tx, snapshot_rx) = watch::channel(Snapshot::default());
+    let (shutdown_tx, shutdown_rx) = watch::channel(false);
+    let metrics = Arc::new(Metrics::default());
+    let mut engine = Engine::from_config(&config);
+    engine.server_rx = Some(server_rx);
+    engine.admin_rx = Some(admin_rx);
+    engine.snapshot_tx = Some(snapshot_tx);
+    engine.metrics = metrics.clone();
+    let mut server = Server::from_config(config.clone()).await;
+    server.engine_tx = Some(Arc::new(engine_tx));
+    server.snapshot_rx = Some(snapshot_rx);
+    server.shutdown_rx = Some(shutdown_rx);
+    server.metrics = metrics.clone();
 
-    pub async fn from_config(config: Config) -> Self {
-        let socket = UdpSocket::bind(config.addr).await.unwrap();
-        println!("Creating socket: {socket:?}");
-        Self {
-            socket,
-            engine_tx: None,
+    if let Some(addr) = config.metrics_addr {
+        match TcpListener::bind(addr).await {
+            Ok(listener) => {
+                info!(%addr, "serving metrics");
+                tokio::spawn(metrics::serve(listener, metrics.clone()));
+            }
+            Err(e) => warn!(%addr, error = %e, "could not serve metrics"),
         }
     }
 
-    pub async fn run(&self) {
-        let mut connections: HashMap<SocketAddr, Arc<Mutex<Connection>>> = HashMap::new();
+    tokio::spawn(watch_config(
+        args,
+        reload::POLL_INTERVAL,
+        reload_rx,
+        admin_tx.clone(),
+    ));
+    tokio::spawn(read_admin_commands(admin_tx.clone(), reload_tx, log));
+    let engine_handle = tokio::spawn(async move { engine.run().await });
+    let server_handle = tokio::spawn(async move { server.run().await });
+    // The engine stops once the server has, after one last tick.
+    let stopped = async { tokio::join!(engine_handle, server_handle) };
+    tokio::pin!(stopped);
 
-        loop {
-            let mut buf = [0; 64];
-            let (n, origin) = match self.socket.recv_from(&mut buf).await {
-                Ok((n, origin)) => (n, origin),
-                Err(e) => {
-                    println!("Got error: {e}");
-                    continue;
+    let (engine_result, server_result) = tokio::select! {
+        results = &mut stopped => results,
+        _ = shutdown_signal() => {
+            info!(deadline = ?config.shutdown_timeout, "shutting down");
+            shutdown_tx.send_replace(true);
+            match timeout(config.shutdown_timeout, &mut stopped).await {
+                Ok(results) => results,
+                Err(_) => {
+                    error!("shutdown took too long, exiting anyway");
+                    log_final_metrics(&metrics);
+                    let _ = std::io::stdout().flush();
+                    std::process::exit(1);
                 }
-            };
-
-            let connection = connections.entry(origin).or_insert({
-                let mut connection = Connection::new(origin);
-                connection.engine_tx = self.engine_tx.clone();
-                let connection = Arc::new(Mutex::new(connection));
-                connection
-            });
-
-            let buf = &buf[..n];
-            let frame = Frame::from_bytes(buf);
-            if let Some(frame) = frame {
-                let connection = connection.clone();
-                tokio::spawn(async move {
-                    let mut lock = connection.lock().await;
-                    lock.handle_frame(frame).await;
-                });
             }
         }
-    }
-}
-
-#[tokio::main()]
-async fn main() {
-    let (engine_tx, server_rx) = channel(5);
-    let mut engine = Engine {
-        server_rx: Some(server_rx),
-        ..Default::default()
     };
-    let mut server = Server::from_config(Config {
-        addr: "127.0.0.1:10001".parse().unwrap(),
-    })
-    .await;
-    server.engine_tx = Some(Arc::new(engine_tx));
-
-    let engine_handle = tokio::spawn(async move { engine.run().await });
-    let server_handle = tokio::spawn(async move { server.run().await });
-    let (engine_result, server_result) = tokio::join!(engine_handle, server_handle);
     engine_result.unwrap();
-    server_result.unwrap();
+    let server_result = server_result.unwrap();
+    if let Err(e) = &server_result {
+        error!(error = %e, "server stopped");
+    }
+    log_final_metrics(&metrics);
+    let _ = std::io::stdout().flush();
+    if server_result.is_err() {
+        std::process::exit(1);
+    }
 }
@@ -0,0 +1,101 @@
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use net::frame::{Frame, FrameRef};
+use net::id::Id;
+use net::packet::movement::Movement;
+use net::packet::snapshot::{EntityState, Snapshot};
+use net::packet::{Packet, PacketRef};
+use net::position::Position;
+
+fn movement_frame() -> Frame {
+    let mut frame = Frame::new();
+    frame.syn = 42;
+    frame.packet = Some(Packet::Movement(Movement {
+        id: Id(7),
+        up: true,
+        right: true,
+        ..Default::default()
+    }));
+    frame
+}
+
+fn snapshot_frame(players: u16) -> Frame {
+    let mut frame = Frame::new();
+    frame.packet = Some(Packet::Snapshot(Snapshot {
+        tick: 1000,
+        players: (0..players)
+            .map(|i| EntityState {
+                id: Id(i),
+                position: Position::new(i as f32, -(i as f32)),
+            })
+            .collect(),
+        ..Default::default()
+    }));
+    frame
+}
+
+fn movement(c: &mut Criterion) {
+    let mut group = c.benchmark_group("movement");
+    let frame = movement_frame();
+    let bytes = frame.to_bytes();
+
+    group.bench_function("to_bytes", |b| b.iter(|| black_box(&frame).to_bytes()));
+    group.bench_function("encode", |b| {
+        let mut buf = Vec::with_capacity(64);
+        b.iter(|| {
+            buf.clear();
+            black_box(&frame).encode(&mut buf)
+        })
+    });
+    group.bench_function("from_bytes", |b| {
+        b.iter(|| Frame::from_bytes(black_box(&bytes)))
+    });
+    group.bench_function("from_bytes_ref", |b| {
+        b.iter(|| FrameRef::from_bytes(black_box(&bytes)))
+    });
+    group.finish();
+}
+
+fn snapshot(c: &mut Criterion) {
+    let mut group = c.benchmark_group("snapshot");
+    for players in [1, 10, 100] {
+        let frame = snapshot_frame(players);
+        let bytes = frame.to_bytes();
+        group.throughput(Throughput::Bytes(bytes.len() as u64));
+
+        group.bench_with_input(BenchmarkId::new("to_bytes", players), &frame, |b, frame| {
+            b.iter(|| frame.to_bytes())
+        });
+        group.bench_with_input(BenchmarkId::new("encode", players), &frame, |b, frame| {
+            let mut buf = Vec::with_capacity(bytes.len());
+            b.iter(|| {
+                buf.clear();
+                frame.encode(&mut buf)
+            })
+        });
+        group.bench_with_input(
+            BenchmarkId::new("from_bytes", players),
+            &bytes,
+            |b, bytes| b.iter(|| Frame::from_bytes(black_box(bytes))),
+        );
+        group.bench_with_input(
+            BenchmarkId::new("from_bytes_ref", players),
+            &bytes,
+            |b, bytes| {
+                b.iter(|| {
+                    let frame = FrameRef::from_bytes(black_box(bytes)).unwrap();
+                    let Some(PacketRef::Snapshot(snapshot)) = frame.packet else {
+                        unreachable!();
+                    };
+                    snapshot
+                        .players()
+                        .map(|player| player.position.x)
+                        .sum::<f32>()
+                })
+            },
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(benches, movement, snapshot);
+criterion_main!(benches);
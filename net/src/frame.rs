@@ -1,7 +1,11 @@
-use crate::packet::Packet;
+use crate::packet::{Packet, PacketRef};
 use tokio_util::bytes::BufMut;
 
-#[derive(Copy, Clone, Debug)]
+/// Largest datagram either side sends, and so the size of every receive buffer. An Ethernet
+/// frame less the IPv4 and UDP headers, so nothing is fragmented on the way.
+pub const MAX_DATAGRAM: usize = 1472;
+
+#[derive(Clone, Debug)]
 pub struct Frame {
     pub version: u8,
     pub syn: u32,
@@ -10,7 +14,9 @@ pub struct Frame {
 }
 
 impl Frame {
-    const PROTOCOL_VERSION: u8 = 1;
+    /// Peers with a different version are turned away.
+    pub const PROTOCOL_VERSION: u8 = 3;
+    pub const HEADER_SIZE: usize = 9;
 
     pub fn new() -> Self {
         Self {
@@ -21,34 +27,70 @@ impl Frame {
         }
     }
 
-    pub fn to_bytes(&self) -> Vec<u8> {
-        let mut output = vec![self.version];
-        let syn = self.syn.to_be_bytes();
-        let ack = self.ack.to_be_bytes();
-        output.put_slice(&syn);
-        output.put_slice(&ack);
-
-        if let Some(packet) = self.packet {
-            let mut packet = match packet {
-                Packet::Ping(ping) => ping.to_bytes(),
-                Packet::Sync(sync) => sync.to_bytes(),
-                Packet::Movement(movement) => movement.to_bytes(),
-            };
-            output.append(&mut packet);
+    pub fn encoded_len(&self) -> usize {
+        Self::HEADER_SIZE + self.packet.as_ref().map_or(0, Packet::encoded_len)
+    }
+
+    /// Writes only the header, for sending the same encoded packet to several peers.
+    pub fn encode_header(&self, buf: &mut impl BufMut) -> usize {
+        buf.put_u8(self.version);
+        buf.put_u32(self.syn);
+        buf.put_u32(self.ack);
+        Self::HEADER_SIZE
+    }
+
+    /// Writes the frame into `buf` and returns how many bytes that took.
+    pub fn encode(&self, buf: &mut impl BufMut) -> usize {
+        let header = self.encode_header(buf);
+        let packet = self.packet.as_ref().map_or(0, |packet| packet.encode(buf));
+        header + packet
+    }
+
+    /// Like `encode`, but returns `None` instead of panicking when `out` is too short.
+    pub fn encode_to_slice(&self, mut out: &mut [u8]) -> Option<usize> {
+        if out.len() < self.encoded_len() {
+            return None;
         }
 
+        Some(self.encode(&mut out))
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut output = Vec::with_capacity(self.encoded_len());
+        self.encode(&mut output);
         output
     }
 
     pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
-        if bytes.is_empty() {
+        FrameRef::from_bytes(bytes).map(|frame| frame.to_owned())
+    }
+}
+
+impl Default for Frame {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A frame decoded without copying its packet out of `bytes`.
+#[derive(Copy, Clone, Debug)]
+pub struct FrameRef<'a> {
+    pub version: u8,
+    pub syn: u32,
+    pub ack: u32,
+    pub packet: Option<PacketRef<'a>>,
+}
+
+impl<'a> FrameRef<'a> {
+    pub fn from_bytes(bytes: &'a [u8]) -> Option<FrameRef<'a>> {
+        if bytes.len() < Frame::HEADER_SIZE {
             return None;
         }
 
         let version = bytes[0];
         let syn = u32::from_be_bytes([bytes[1], bytes[2], bytes[3], bytes[4]]);
         let ack = u32::from_be_bytes([bytes[5], bytes[6], bytes[7], bytes[8]]);
-        let packet = Packet::from_bytes(&bytes[9..]);
+        let packet = PacketRef::from_bytes(&bytes[Frame::HEADER_SIZE..]);
 
         Some(Self {
             version,
@@ -57,10 +99,49 @@ impl Frame {
             packet,
         })
     }
+
+    pub fn to_owned(&self) -> Frame {
+        Frame {
+            version: self.version,
+            syn: self.syn,
+            ack: self.ack,
+            packet: self.packet.as_ref().map(PacketRef::to_owned),
+        }
+    }
 }
 
-impl Default for Frame {
-    fn default() -> Self {
-        Self::new()
+#[cfg(test)]
+mod tests {
+    mod encode {
+        use crate::frame::{Frame, FrameRef};
+        use crate::packet::ping::Ping;
+        use crate::packet::Packet;
+
+        #[test]
+        fn encode_to_slice_reports_length() {
+            let mut frame = Frame::new();
+            frame.syn = 7;
+            frame.packet = Some(Packet::Ping(Ping));
+
+            let mut out = [0; 64];
+            let n = frame.encode_to_slice(&mut out).unwrap();
+            assert_eq!(n, 10);
+            assert_eq!(&out[..n], [3, 0, 0, 0, 7, 0, 0, 0, 0, 0]);
+            assert_eq!(&out[..n], frame.to_bytes());
+        }
+
+        #[test]
+        fn encode_to_short_slice_fails() {
+            let mut frame = Frame::new();
+            frame.packet = Some(Packet::Ping(Ping));
+
+            let mut out = [0; 9];
+            assert_eq!(frame.encode_to_slice(&mut out), None);
+        }
+
+        #[test]
+        fn short_frame_is_rejected() {
+            assert!(FrameRef::from_bytes(&[1, 0, 0, 0]).is_none());
+        }
     }
 }
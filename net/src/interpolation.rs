@@ -0,0 +1,275 @@
+use crate::id::Id;
+use crate::position::Position;
+use std::collections::{HashMap, VecDeque};
+use wasm_bindgen::prelude::wasm_bindgen;
+
+/// How far behind the latest server time remote entities are rendered, in milliseconds.
+/// Two snapshot intervals at 20 Hz, so a single lost snapshot does not cause extrapolation.
+pub const DEFAULT_DELAY: f64 = 100.0;
+/// How long past the newest snapshot an entity keeps moving before it is frozen, in milliseconds.
+pub const DEFAULT_MAX_EXTRAPOLATION: f64 = 250.0;
+/// Any jump between two consecutive snapshots longer than this is treated as a teleport.
+pub const DEFAULT_TELEPORT_DISTANCE: f32 = 5.0;
+pub const DEFAULT_CAPACITY: usize = 32;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Snapshot {
+    /// Server time in milliseconds.
+    pub time: f64,
+    pub position: Position,
+}
+
+/// Buffers timestamped snapshots of remote entities and renders them a fixed delay behind server
+/// time, so that they move smoothly between updates.
+#[derive(Debug)]
+#[wasm_bindgen]
+pub struct Interpolator {
+    delay: f64,
+    max_extrapolation: f64,
+    teleport_distance: f32,
+    capacity: usize,
+    buffers: HashMap<Id, VecDeque<Snapshot>>,
+}
+
+#[wasm_bindgen]
+impl Interpolator {
+    #[wasm_bindgen(constructor)]
+    pub fn new(delay: f64, max_extrapolation: f64, teleport_distance: f32) -> Interpolator {
+        Interpolator {
+            delay,
+            max_extrapolation,
+            teleport_distance,
+            capacity: DEFAULT_CAPACITY,
+            buffers: HashMap::new(),
+        }
+    }
+
+    pub fn delay(&self) -> f64 {
+        self.delay
+    }
+
+    pub fn set_delay(&mut self, delay: f64) {
+        self.delay = delay;
+    }
+
+    /// Records the `position` of `id` at server `time`. Snapshots may arrive out of order, a
+    /// snapshot with the same time as an already buffered one replaces it.
+    pub fn push(&mut self, id: Id, time: f64, position: Position) {
+        let buffer = self.buffers.entry(id).or_default();
+        let snapshot = Snapshot { time, position };
+
+        let index = buffer.partition_point(|s| s.time < time);
+        match buffer.get_mut(index) {
+            Some(existing) if existing.time == time => *existing = snapshot,
+            _ => buffer.insert(index, snapshot),
+        }
+
+        while buffer.len() > self.capacity {
+            buffer.pop_front();
+        }
+    }
+
+    pub fn remove(&mut self, id: Id) {
+        self.buffers.remove(&id);
+    }
+
+    pub fn clear(&mut self) {
+        self.buffers.clear();
+    }
+
+    /// Position of `id` to render when the latest known server time is `server_time`.
+    pub fn sample(&self, id: Id, server_time: f64) -> Option<Position> {
+        let buffer = self.buffers.get(&id)?;
+        let render_time = server_time - self.delay;
+
+        let index = buffer.partition_point(|s| s.time <= render_time);
+        if index == 0 {
+            return buffer.front().map(|s| s.position);
+        }
+
+        let from = buffer[index - 1];
+        match buffer.get(index) {
+            Some(&to) => Some(self.interpolate(from, to, render_time)),
+            None => Some(self.extrapolate(buffer, render_time)),
+        }
+    }
+}
+
+impl Interpolator {
+    pub fn with_capacity(mut self, capacity: usize) -> Self {
+        self.capacity = capacity.max(2);
+        self
+    }
+
+    pub fn ids(&self) -> impl Iterator<Item = Id> + '_ {
+        self.buffers.keys().copied()
+    }
+
+    /// Positions of every buffered entity at `server_time`.
+    pub fn sample_all(&self, server_time: f64) -> impl Iterator<Item = (Id, Position)> + '_ {
+        self.ids()
+            .filter_map(move |id| Some((id, self.sample(id, server_time)?)))
+    }
+
+    fn is_teleport(&self, from: &Snapshot, to: &Snapshot) -> bool {
+        from.position.distance(&to.position) > self.teleport_distance
+    }
+
+    fn interpolate(&self, from: Snapshot, to: Snapshot, render_time: f64) -> Position {
+        if self.is_teleport(&from, &to) {
+            return from.position;
+        }
+
+        let t = (render_time - from.time) / (to.time - from.time);
+        from.position.lerp(&to.position, t as f32)
+    }
+
+    fn extrapolate(&self, buffer: &VecDeque<Snapshot>, render_time: f64) -> Position {
+        let latest = buffer[buffer.len() - 1];
+        if buffer.len() < 2 {
+            return latest.position;
+        }
+
+        let previous = buffer[buffer.len() - 2];
+        if self.is_teleport(&previous, &latest) {
+            return latest.position;
+        }
+
+        let ahead = (render_time - latest.time).min(self.max_extrapolation);
+        let t = 1.0 + ahead / (latest.time - previous.time);
+        previous.position.lerp(&latest.position, t as f32)
+    }
+}
+
+impl Default for Interpolator {
+    fn default() -> Self {
+        Self::new(
+            DEFAULT_DELAY,
+            DEFAULT_MAX_EXTRAPOLATION,
+            DEFAULT_TELEPORT_DISTANCE,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    mod behavior {
+        use crate::id::Id;
+        use crate::interpolation::Interpolator;
+        use crate::position::Position;
+
+        fn interpolator() -> Interpolator {
+            Interpolator::new(100.0, 50.0, 5.0)
+        }
+
+        #[test]
+        fn unknown_id_has_no_position() {
+            let interpolator = interpolator();
+            assert_eq!(interpolator.sample(Id(1), 1000.0), None);
+        }
+
+        #[test]
+        fn renders_behind_server_time() {
+            let mut interpolator = interpolator();
+            let id = Id(1);
+            interpolator.push(id, 0.0, Position::new(0.0, 0.0));
+            interpolator.push(id, 50.0, Position::new(1.0, 2.0));
+            interpolator.push(id, 100.0, Position::new(2.0, 4.0));
+
+            assert_eq!(
+                interpolator.sample(id, 125.0),
+                Some(Position::new(0.5, 1.0))
+            );
+            assert_eq!(
+                interpolator.sample(id, 150.0),
+                Some(Position::new(1.0, 2.0))
+            );
+            assert_eq!(
+                interpolator.sample(id, 175.0),
+                Some(Position::new(1.5, 3.0))
+            );
+        }
+
+        #[test]
+        fn holds_first_snapshot_before_buffer_fills() {
+            let mut interpolator = interpolator();
+            let id = Id(1);
+            interpolator.push(id, 1000.0, Position::new(3.0, 3.0));
+
+            assert_eq!(
+                interpolator.sample(id, 1000.0),
+                Some(Position::new(3.0, 3.0))
+            );
+        }
+
+        #[test]
+        fn out_of_order_snapshots_are_sorted() {
+            let mut interpolator = interpolator();
+            let id = Id(1);
+            interpolator.push(id, 100.0, Position::new(2.0, 0.0));
+            interpolator.push(id, 0.0, Position::new(0.0, 0.0));
+            interpolator.push(id, 50.0, Position::new(1.0, 0.0));
+
+            assert_eq!(
+                interpolator.sample(id, 175.0),
+                Some(Position::new(1.5, 0.0))
+            );
+        }
+
+        #[test]
+        fn extrapolates_for_a_limited_time() {
+            let mut interpolator = interpolator();
+            let id = Id(1);
+            interpolator.push(id, 0.0, Position::new(0.0, 0.0));
+            interpolator.push(id, 50.0, Position::new(1.0, 0.0));
+
+            assert_eq!(
+                interpolator.sample(id, 175.0),
+                Some(Position::new(1.5, 0.0))
+            );
+            assert_eq!(
+                interpolator.sample(id, 200.0),
+                Some(Position::new(2.0, 0.0))
+            );
+            assert_eq!(
+                interpolator.sample(id, 1000.0),
+                Some(Position::new(2.0, 0.0))
+            );
+        }
+
+        #[test]
+        fn snaps_on_teleport() {
+            let mut interpolator = interpolator();
+            let id = Id(1);
+            interpolator.push(id, 0.0, Position::new(0.0, 0.0));
+            interpolator.push(id, 50.0, Position::new(100.0, 0.0));
+
+            assert_eq!(
+                interpolator.sample(id, 125.0),
+                Some(Position::new(0.0, 0.0))
+            );
+            assert_eq!(
+                interpolator.sample(id, 150.0),
+                Some(Position::new(100.0, 0.0))
+            );
+            assert_eq!(
+                interpolator.sample(id, 175.0),
+                Some(Position::new(100.0, 0.0))
+            );
+        }
+
+        #[test]
+        fn capacity_drops_oldest_snapshots() {
+            let mut interpolator = interpolator().with_capacity(2);
+            let id = Id(1);
+            interpolator.push(id, 0.0, Position::new(0.0, 0.0));
+            interpolator.push(id, 50.0, Position::new(1.0, 0.0));
+            interpolator.push(id, 100.0, Position::new(2.0, 0.0));
+
+            assert_eq!(
+                interpolator.sample(id, 100.0),
+                Some(Position::new(1.0, 0.0))
+            );
+        }
+    }
+}
@@ -1,3 +1,6 @@
 pub mod frame;
 pub mod id;
+pub mod interpolation;
+pub mod nickname;
 pub mod packet;
+pub mod position;
@@ -0,0 +1,93 @@
+use std::fmt::{Debug, Display, Formatter};
+
+/// A player's display name, stored inline so packets carrying it stay `Copy`.
+#[derive(Copy, Clone, Default, Eq, PartialEq)]
+pub struct Nickname {
+    len: u8,
+    bytes: [u8; Nickname::MAX_LEN],
+}
+
+impl Nickname {
+    /// In bytes of UTF-8.
+    pub const MAX_LEN: usize = 16;
+
+    /// Longer names are cut at the last character that fits.
+    pub fn new(name: &str) -> Self {
+        let mut len = name.len().min(Self::MAX_LEN);
+        while !name.is_char_boundary(len) {
+            len -= 1;
+        }
+
+        let mut bytes = [0; Self::MAX_LEN];
+        bytes[..len].copy_from_slice(&name.as_bytes()[..len]);
+        Self {
+            len: len as u8,
+            bytes,
+        }
+    }
+
+    /// `None` if `bytes` is too long or not UTF-8.
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() > Self::MAX_LEN {
+            return None;
+        }
+        Some(Self::new(std::str::from_utf8(bytes).ok()?))
+    }
+
+    pub fn as_str(&self) -> &str {
+        // Only ever built from whole characters of a `str`.
+        std::str::from_utf8(self.as_bytes()).unwrap_or_default()
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes[..self.len as usize]
+    }
+
+    pub fn len(&self) -> usize {
+        self.len as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
+impl Display for Nickname {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl Debug for Nickname {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        Debug::fmt(self.as_str(), f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    mod behavior {
+        use crate::nickname::Nickname;
+
+        #[test]
+        fn long_names_are_cut_between_characters() {
+            assert_eq!(Nickname::new("alice").as_str(), "alice");
+            assert_eq!(
+                Nickname::new("abcdefghijklmnopqrstuvwxyz").as_str(),
+                "abcdefghijklmnop"
+            );
+            // 'é' takes two bytes and would straddle the limit.
+            assert_eq!(
+                Nickname::new("abcdefghijklmnoé").as_str(),
+                "abcdefghijklmno"
+            );
+        }
+
+        #[test]
+        fn from_bytes_rejects_invalid_names() {
+            assert_eq!(Nickname::from_bytes(b"bob"), Some(Nickname::new("bob")));
+            assert_eq!(Nickname::from_bytes(&[0xff, 0xfe]), None);
+            assert_eq!(Nickname::from_bytes(&[b'a'; 17]), None);
+        }
+    }
+}
@@ -1,41 +1,117 @@
+use crate::packet::disconnect::{Disconnect, DisconnectRef};
+use crate::packet::join::Join;
 use crate::packet::movement::Movement;
 use crate::packet::ping::Ping;
+use crate::packet::snapshot::{Snapshot, SnapshotRef};
 use crate::packet::sync::Sync;
+use crate::packet::welcome::Welcome;
+use tokio_util::bytes::BufMut;
 
+pub mod disconnect;
+pub mod join;
 pub mod movement;
 pub mod ping;
+pub mod snapshot;
 pub mod sync;
+pub mod welcome;
 
-#[derive(Copy, Clone, Debug)]
+#[derive(Clone, Debug)]
 #[repr(u8)]
 pub enum Packet {
     Ping(Ping) = 0,
     Sync(Sync) = 1,
     Movement(Movement) = 2,
+    Snapshot(Snapshot) = 3,
+    Join(Join) = 4,
+    Welcome(Welcome) = 5,
+    Disconnect(Disconnect) = 6,
 }
 
 impl Packet {
-    pub fn to_bytes(&self) -> Vec<u8> {
+    pub fn encoded_len(&self) -> usize {
+        match self {
+            Packet::Ping(_) => Ping::SIZE,
+            Packet::Sync(_) => Sync::SIZE,
+            Packet::Movement(_) => Movement::SIZE,
+            Packet::Snapshot(snapshot) => snapshot.encoded_len(),
+            Packet::Join(join) => join.encoded_len(),
+            Packet::Welcome(_) => Welcome::SIZE,
+            Packet::Disconnect(disconnect) => disconnect.encoded_len(),
+        }
+    }
+
+    /// Writes the packet into `buf` and returns how many bytes that took.
+    pub fn encode(&self, buf: &mut impl BufMut) -> usize {
         match self {
-            Packet::Ping(ping) => ping.to_bytes(),
-            Packet::Sync(sync) => sync.to_bytes(),
-            Packet::Movement(movement) => movement.to_bytes().to_vec(),
+            Packet::Ping(ping) => ping.encode(buf),
+            Packet::Sync(sync) => sync.encode(buf),
+            Packet::Movement(movement) => movement.encode(buf),
+            Packet::Snapshot(snapshot) => snapshot.encode(buf),
+            Packet::Join(join) => join.encode(buf),
+            Packet::Welcome(welcome) => welcome.encode(buf),
+            Packet::Disconnect(disconnect) => disconnect.encode(buf),
         }
     }
 
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut output = Vec::with_capacity(self.encoded_len());
+        self.encode(&mut output);
+        output
+    }
+
     pub fn from_bytes(bytes: &[u8]) -> Option<Packet> {
+        PacketRef::from_bytes(bytes).map(|packet| packet.to_owned())
+    }
+}
+
+/// A packet decoded without copying variable length data out of `bytes`.
+#[derive(Copy, Clone, Debug)]
+pub enum PacketRef<'a> {
+    Ping(Ping),
+    Sync(Sync),
+    Movement(Movement),
+    Snapshot(SnapshotRef<'a>),
+    Join(Join),
+    Welcome(Welcome),
+    Disconnect(DisconnectRef<'a>),
+}
+
+impl<'a> PacketRef<'a> {
+    pub fn from_bytes(bytes: &'a [u8]) -> Option<PacketRef<'a>> {
         if bytes.is_empty() {
             return None;
         }
 
         let packet_id = bytes[0];
         match packet_id {
-            ping::PING_PACKET_ID => Some(Packet::Ping(Ping)),
-            sync::SYNC_PACKET_ID => Some(Packet::Sync(Sync)),
+            ping::PING_PACKET_ID => Some(PacketRef::Ping(Ping)),
+            sync::SYNC_PACKET_ID => Some(PacketRef::Sync(Sync)),
             movement::MOVEMENT_PACKET_ID => {
-                Some(Packet::Movement(Movement::from_bytes(&bytes[1..])?))
+                Some(PacketRef::Movement(Movement::from_bytes(&bytes[1..])?))
+            }
+            snapshot::SNAPSHOT_PACKET_ID => {
+                Some(PacketRef::Snapshot(SnapshotRef::from_bytes(&bytes[1..])?))
             }
-            _ => todo!("Unknown packet id: {packet_id}"),
+            join::JOIN_PACKET_ID => Some(PacketRef::Join(Join::from_bytes(&bytes[1..])?)),
+            welcome::WELCOME_PACKET_ID => {
+                Some(PacketRef::Welcome(Welcome::from_bytes(&bytes[1..])?))
+            }
+            disconnect::DISCONNECT_PACKET_ID => Some(PacketRef::Disconnect(
+                DisconnectRef::from_bytes(&bytes[1..])?,
+            )),
+            _ => None,
+        }
+    }
+
+    pub fn to_owned(&self) -> Packet {
+        match *self {
+            PacketRef::Ping(ping) => Packet::Ping(ping),
+            PacketRef::Sync(sync) => Packet::Sync(sync),
+            PacketRef::Movement(movement) => Packet::Movement(movement),
+            PacketRef::Snapshot(snapshot) => Packet::Snapshot(snapshot.to_owned()),
+            PacketRef::Join(join) => Packet::Join(join),
+            PacketRef::Welcome(welcome) => Packet::Welcome(welcome),
+            PacketRef::Disconnect(disconnect) => Packet::Disconnect(disconnect.to_owned()),
         }
     }
 }
@@ -45,6 +121,8 @@ mod tests {
     mod byte_order {
         use crate::id::Id;
         use crate::packet::movement::Movement;
+        use crate::packet::snapshot::{EntityState, Snapshot};
+        use crate::position::Position;
 
         #[test]
         fn move_command_byte_order() {
@@ -58,5 +136,243 @@ mod tests {
             let byte = command.to_bytes();
             assert_eq!(byte, [2, 0, 1, 0b1000]);
         }
+
+        #[test]
+        fn snapshot_byte_order() {
+            let snapshot = Snapshot {
+                tick: 258,
+                players: vec![EntityState {
+                    id: Id(1),
+                    position: Position { x: -10.0, y: 25.0 },
+                }],
+                entities: vec![EntityState {
+                    id: Id(2),
+                    position: Position { x: 0.0, y: 0.0 },
+                }],
+            };
+            assert_eq!(
+                snapshot.to_bytes(),
+                [
+                    3, 0, 0, 1, 2, 0, 1, 0, 1, 0b11000001, 0b00100000, 0b00000000, 0b00000000,
+                    0b01000001, 0b11001000, 0b00000000, 0b00000000, 0, 1, 0, 2, 0, 0, 0, 0, 0, 0,
+                    0, 0
+                ]
+            );
+        }
+    }
+
+    mod round_trip {
+        use crate::id::Id;
+        use crate::packet::disconnect::{Disconnect, Reason};
+        use crate::packet::join::Join;
+        use crate::packet::snapshot::{EntityState, Snapshot};
+        use crate::packet::welcome::Welcome;
+        use crate::packet::{Packet, PacketRef};
+        use crate::position::Position;
+
+        #[test]
+        fn snapshot() {
+            let snapshot = Snapshot {
+                tick: 42,
+                players: vec![
+                    EntityState {
+                        id: Id(1),
+                        position: Position { x: 1.5, y: -2.0 },
+                    },
+                    EntityState {
+                        id: Id(7),
+                        position: Position { x: 0.0, y: 3.25 },
+                    },
+                ],
+                entities: vec![EntityState {
+                    id: Id(1),
+                    position: Position { x: 4.0, y: 4.0 },
+                }],
+            };
+            let bytes = Packet::Snapshot(snapshot.clone()).to_bytes();
+            let Some(Packet::Snapshot(decoded)) = Packet::from_bytes(&bytes) else {
+                panic!("Expected a snapshot");
+            };
+            assert_eq!(decoded, snapshot);
+        }
+
+        #[test]
+        fn truncated_snapshot_is_rejected() {
+            let snapshot = Snapshot {
+                tick: 1,
+                players: vec![EntityState::default()],
+                entities: vec![EntityState::default()],
+            };
+            let bytes = snapshot.to_bytes();
+            assert!(Packet::from_bytes(&bytes[..bytes.len() - 1]).is_none());
+            // Without the entity count at all.
+            assert!(Packet::from_bytes(&bytes[..bytes.len() - EntityState::SIZE - 2]).is_none());
+
+            let mut bytes = bytes;
+            bytes.push(0);
+            assert!(Packet::from_bytes(&bytes).is_none());
+        }
+
+        #[test]
+        fn handshake() {
+            let mut join = Join::new(10, "alice");
+            join.token = 0x0102_0304_0506_0708;
+            let Some(Packet::Join(decoded)) = Packet::from_bytes(&join.to_bytes()) else {
+                panic!("Expected a join");
+            };
+            assert_eq!(decoded, join);
+
+            let welcome = Welcome {
+                id: Id(12),
+                tick_rate: 60.0,
+                snapshot_rate: 20.0,
+                token: u64::MAX,
+            };
+            let Some(Packet::Welcome(decoded)) = Packet::from_bytes(&welcome.to_bytes()) else {
+                panic!("Expected a welcome");
+            };
+            assert_eq!(decoded, welcome);
+
+            let mut bytes = join.to_bytes();
+            bytes.pop();
+            assert!(Packet::from_bytes(&bytes).is_none());
+        }
+
+        #[test]
+        fn disconnect() {
+            for disconnect in [
+                Disconnect::new(Reason::ServerShutdown),
+                Disconnect::with_message(Reason::Kicked, "spamming"),
+            ] {
+                let Some(Packet::Disconnect(decoded)) = Packet::from_bytes(&disconnect.to_bytes())
+                else {
+                    panic!("Expected a disconnect");
+                };
+                assert_eq!(decoded, disconnect);
+            }
+
+            // Unknown reason, message longer than its length says, and a message that is not
+            // UTF-8.
+            assert!(Packet::from_bytes(&[6, 200, 0]).is_none());
+            assert!(Packet::from_bytes(&[6, 1, 1, b'a', b'b']).is_none());
+            assert!(Packet::from_bytes(&[6, 1, 1, 0xFF]).is_none());
+        }
+
+        #[test]
+        fn long_disconnect_messages_are_cut_between_characters() {
+            let message = "é".repeat(200);
+            let disconnect = Disconnect::with_message(Reason::Banned, &message);
+            assert_eq!(disconnect.message.as_deref().map(str::len), Some(254));
+            assert_eq!(Disconnect::with_message(Reason::Quit, "").message, None);
+        }
+
+        #[test]
+        fn long_disconnect_messages_set_directly_are_cut_when_encoded() {
+            let disconnect = Disconnect {
+                reason: Reason::Kicked,
+                message: Some("é".repeat(200)),
+            };
+            let bytes = disconnect.to_bytes();
+            assert_eq!(bytes.len(), disconnect.encoded_len());
+            let Some(Packet::Disconnect(decoded)) = Packet::from_bytes(&bytes) else {
+                panic!("Expected a disconnect");
+            };
+            assert_eq!(
+                decoded,
+                Disconnect::with_message(Reason::Kicked, &"é".repeat(200))
+            );
+        }
+
+        #[test]
+        fn snapshot_can_be_read_in_place() {
+            let snapshot = Snapshot {
+                tick: 9,
+                players: vec![
+                    EntityState {
+                        id: Id(3),
+                        position: Position { x: 1.0, y: 2.0 },
+                    },
+                    EntityState {
+                        id: Id(4),
+                        position: Position { x: -1.0, y: -2.0 },
+                    },
+                ],
+                entities: vec![EntityState::default(); 3],
+            };
+            let bytes = snapshot.to_bytes();
+            let Some(PacketRef::Snapshot(view)) = PacketRef::from_bytes(&bytes) else {
+                panic!("Expected a snapshot");
+            };
+            assert_eq!(view.tick, 9);
+            assert_eq!(view.len(), 2);
+            assert_eq!(view.players().collect::<Vec<_>>(), snapshot.players);
+            assert_eq!(view.entities().collect::<Vec<_>>(), snapshot.entities);
+        }
+
+        #[test]
+        fn unknown_packet_id_is_rejected() {
+            assert!(Packet::from_bytes(&[255, 1, 2, 3]).is_none());
+        }
+    }
+
+    mod encode {
+        use crate::frame::{Frame, MAX_DATAGRAM};
+        use crate::id::Id;
+        use crate::packet::disconnect::{Disconnect, Reason};
+        use crate::packet::join::Join;
+        use crate::packet::movement::Movement;
+        use crate::packet::snapshot::{EntityState, Snapshot};
+        use crate::packet::welcome::Welcome;
+        use crate::packet::Packet;
+
+        #[test]
+        fn encoded_len_matches_bytes_written() {
+            let packets = [
+                Packet::Movement(Movement::default()),
+                Packet::Welcome(Welcome::default()),
+                Packet::Disconnect(Disconnect::with_message(Reason::RoomFull, "try later")),
+                Packet::Join(Join::new(0, "bob")),
+                Packet::Snapshot(Snapshot {
+                    tick: 1,
+                    players: vec![EntityState::default(); 3],
+                    entities: vec![EntityState::default(); 2],
+                }),
+            ];
+            for packet in packets {
+                let mut buf = vec![];
+                let written = packet.encode(&mut buf);
+                assert_eq!(written, buf.len());
+                assert_eq!(written, packet.encoded_len());
+            }
+        }
+
+        #[test]
+        fn fullest_snapshot_fits_a_datagram() {
+            let mut frame = Frame::new();
+            frame.packet = Some(Packet::Snapshot(Snapshot {
+                tick: 1,
+                players: vec![EntityState::default(); Snapshot::MAX_PLAYERS],
+                ..Default::default()
+            }));
+            assert!(frame.encoded_len() <= MAX_DATAGRAM);
+
+            let Some(Packet::Snapshot(snapshot)) = &mut frame.packet else {
+                unreachable!();
+            };
+            snapshot.players.push(EntityState::default());
+            assert!(frame.encoded_len() > MAX_DATAGRAM);
+        }
+
+        #[test]
+        fn encode_into_existing_buffer_appends() {
+            let mut buf = vec![0xAA];
+            let movement = Movement {
+                id: Id(1),
+                down: true,
+                ..Default::default()
+            };
+            movement.encode(&mut buf);
+            assert_eq!(buf, [0xAA, 2, 0, 1, 0b0100]);
+        }
     }
 }
@@ -0,0 +1,171 @@
+use std::fmt::{Display, Formatter};
+use tokio_util::bytes::BufMut;
+use wasm_bindgen::prelude::wasm_bindgen;
+
+pub const DISCONNECT_PACKET_ID: u8 = 6;
+/// Times a `Disconnect` is sent, since any one of them may get lost.
+pub const DISCONNECT_REPEATS: usize = 3;
+
+/// Why a session ended.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+#[wasm_bindgen]
+#[repr(u8)]
+pub enum Reason {
+    /// The server is stopping.
+    #[default]
+    ServerShutdown = 0,
+    /// The client left on purpose.
+    Quit = 1,
+    /// Removed by an operator, free to join again.
+    Kicked = 2,
+    /// Removed by an operator, and joining again will not work.
+    Banned = 3,
+    /// Nothing arrived from the peer for too long.
+    Timeout = 4,
+    /// The peer speaks a different protocol version.
+    VersionMismatch = 5,
+    /// Every player slot is taken.
+    RoomFull = 6,
+}
+
+impl Reason {
+    pub fn from_u8(code: u8) -> Option<Reason> {
+        match code {
+            0 => Some(Reason::ServerShutdown),
+            1 => Some(Reason::Quit),
+            2 => Some(Reason::Kicked),
+            3 => Some(Reason::Banned),
+            4 => Some(Reason::Timeout),
+            5 => Some(Reason::VersionMismatch),
+            6 => Some(Reason::RoomFull),
+            _ => None,
+        }
+    }
+}
+
+impl Display for Reason {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let reason = match self {
+            Reason::ServerShutdown => "the server shut down",
+            Reason::Quit => "quit",
+            Reason::Kicked => "kicked",
+            Reason::Banned => "banned",
+            Reason::Timeout => "timed out",
+            Reason::VersionMismatch => "client and server versions differ",
+            Reason::RoomFull => "the server is full",
+        };
+        f.write_str(reason)
+    }
+}
+
+/// Ends a session, in either direction. Datagrams get lost, so it is sent `DISCONNECT_REPEATS`
+/// times and may arrive more than once.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Disconnect {
+    pub reason: Reason,
+    /// Said to the player as well as the reason, at most `MAX_MESSAGE_LEN` bytes.
+    pub message: Option<String>,
+}
+
+impl Disconnect {
+    /// In bytes of UTF-8.
+    pub const MAX_MESSAGE_LEN: usize = u8::MAX as usize;
+    const HEADER_SIZE: usize = 3;
+
+    pub fn new(reason: Reason) -> Disconnect {
+        Disconnect {
+            reason,
+            message: None,
+        }
+    }
+
+    /// Longer messages are cut at the last character that fits, an empty one is no message.
+    pub fn with_message(reason: Reason, message: &str) -> Disconnect {
+        let message = fit(message);
+        Disconnect {
+            reason,
+            message: (!message.is_empty()).then(|| message.to_string()),
+        }
+    }
+
+    pub fn encoded_len(&self) -> usize {
+        Self::HEADER_SIZE + self.encoded_message().len()
+    }
+
+    /// A `message` set directly may be too long, it is cut the way `with_message` would.
+    pub fn encode(&self, buf: &mut impl BufMut) -> usize {
+        let message = self.encoded_message().as_bytes();
+        buf.put_u8(DISCONNECT_PACKET_ID);
+        buf.put_u8(self.reason as u8);
+        buf.put_u8(message.len() as u8);
+        buf.put_slice(message);
+        self.encoded_len()
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut output = Vec::with_capacity(self.encoded_len());
+        self.encode(&mut output);
+        output
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Option<Disconnect> {
+        DisconnectRef::from_bytes(bytes).map(|disconnect| disconnect.to_owned())
+    }
+
+    fn encoded_message(&self) -> &str {
+        fit(self.message.as_deref().unwrap_or_default())
+    }
+}
+
+/// The longest start of `message` that is at most `Disconnect::MAX_MESSAGE_LEN` bytes and ends
+/// between characters.
+fn fit(message: &str) -> &str {
+    let mut len = message.len().min(Disconnect::MAX_MESSAGE_LEN);
+    while !message.is_char_boundary(len) {
+        len -= 1;
+    }
+    &message[..len]
+}
+
+impl Display for Disconnect {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match &self.message {
+            Some(message) => write!(f, "{}: {message}", self.reason),
+            None => write!(f, "{}", self.reason),
+        }
+    }
+}
+
+/// A disconnect whose message has not been copied out of the datagram it arrived in.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct DisconnectRef<'a> {
+    pub reason: Reason,
+    pub message: Option<&'a str>,
+}
+
+impl<'a> DisconnectRef<'a> {
+    pub fn from_bytes(bytes: &'a [u8]) -> Option<DisconnectRef<'a>> {
+        if bytes.len() < Disconnect::HEADER_SIZE - 1 {
+            return None;
+        }
+
+        let reason = Reason::from_u8(bytes[0])?;
+        let message = &bytes[2..];
+        if message.len() != bytes[1] as usize {
+            return None;
+        }
+        let message = std::str::from_utf8(message).ok()?;
+
+        Some(Self {
+            reason,
+            message: (!message.is_empty()).then_some(message),
+        })
+    }
+
+    pub fn to_owned(&self) -> Disconnect {
+        Disconnect {
+            reason: self.reason,
+            message: self.message.map(str::to_string),
+        }
+    }
+}
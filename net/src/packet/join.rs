@@ -0,0 +1,69 @@
+use crate::nickname::Nickname;
+use tokio_util::bytes::BufMut;
+use wasm_bindgen::prelude::wasm_bindgen;
+
+pub const JOIN_PACKET_ID: u8 = 4;
+
+/// Sent by a client to start a session. `snapshot_rate` is how many snapshots per second the
+/// client would like to receive, `0` leaves it up to the server.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+#[wasm_bindgen]
+pub struct Join {
+    pub snapshot_rate: u8,
+    /// The token from an earlier `Welcome`, to get the same player back after losing the
+    /// connection. `0` starts a new session.
+    pub token: u64,
+    #[wasm_bindgen(skip)]
+    pub nickname: Nickname,
+}
+
+#[wasm_bindgen]
+impl Join {
+    #[wasm_bindgen(constructor)]
+    pub fn new(snapshot_rate: u8, nickname: &str) -> Join {
+        Join {
+            snapshot_rate,
+            token: 0,
+            nickname: Nickname::new(nickname),
+        }
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut output = Vec::with_capacity(self.encoded_len());
+        self.encode(&mut output);
+        output
+    }
+}
+
+impl Join {
+    const HEADER_SIZE: usize = 11;
+
+    pub fn encoded_len(&self) -> usize {
+        Self::HEADER_SIZE + self.nickname.len()
+    }
+
+    pub fn encode(&self, buf: &mut impl BufMut) -> usize {
+        buf.put_u8(JOIN_PACKET_ID);
+        buf.put_u8(self.snapshot_rate);
+        buf.put_u64(self.token);
+        buf.put_u8(self.nickname.len() as u8);
+        buf.put_slice(self.nickname.as_bytes());
+        self.encoded_len()
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Option<Join> {
+        let [snapshot_rate, token @ .., len] = bytes.get(..Self::HEADER_SIZE - 1)? else {
+            return None;
+        };
+        let nickname = &bytes[Self::HEADER_SIZE - 1..];
+        if nickname.len() != *len as usize {
+            return None;
+        }
+
+        Some(Self {
+            snapshot_rate: *snapshot_rate,
+            token: u64::from_be_bytes(token.try_into().ok()?),
+            nickname: Nickname::from_bytes(nickname)?,
+        })
+    }
+}
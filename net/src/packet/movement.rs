@@ -1,7 +1,8 @@
 use crate::id::Id;
+use tokio_util::bytes::BufMut;
 use wasm_bindgen::prelude::wasm_bindgen;
 
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, PartialEq)]
 #[wasm_bindgen]
 pub struct Movement {
     pub id: Id,
@@ -26,18 +27,8 @@ impl Movement {
     }
 
     pub fn to_bytes(&self) -> Vec<u8> {
-        let up_flag: u8 = if self.up { 0b1000 } else { 0 };
-        let down_flag: u8 = if self.down { 0b0100 } else { 0 };
-        let left_flag: u8 = if self.left { 0b0010 } else { 0 };
-        let right_flag: u8 = if self.right { 0b0001 } else { 0 };
-        let flags = (up_flag | down_flag | left_flag | right_flag) & 0b1111;
-
-        let mut id = self.id.0.to_be_bytes().to_vec();
-
-        let mut output = vec![MOVEMENT_PACKET_ID];
-        output.append(&mut id);
-        output.push(flags);
-
+        let mut output = Vec::with_capacity(Self::SIZE);
+        self.encode(&mut output);
         output
     }
 
@@ -63,3 +54,27 @@ impl Movement {
         })
     }
 }
+
+impl Movement {
+    pub const SIZE: usize = 4;
+
+    pub fn encode(&self, buf: &mut impl BufMut) -> usize {
+        let up_flag: u8 = if self.up { 0b1000 } else { 0 };
+        let down_flag: u8 = if self.down { 0b0100 } else { 0 };
+        let left_flag: u8 = if self.left { 0b0010 } else { 0 };
+        let right_flag: u8 = if self.right { 0b0001 } else { 0 };
+        let flags = (up_flag | down_flag | left_flag | right_flag) & 0b1111;
+
+        buf.put_u8(MOVEMENT_PACKET_ID);
+        buf.put_slice(&self.id.as_bytes());
+        buf.put_u8(flags);
+
+        Self::SIZE
+    }
+}
+
+impl Default for Movement {
+    fn default() -> Self {
+        Self::new()
+    }
+}
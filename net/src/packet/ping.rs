@@ -1,3 +1,4 @@
+use tokio_util::bytes::BufMut;
 use wasm_bindgen::prelude::wasm_bindgen;
 
 #[derive(Copy, Clone, Debug)]
@@ -12,3 +13,12 @@ impl Ping {
         vec![PING_PACKET_ID]
     }
 }
+
+impl Ping {
+    pub const SIZE: usize = 1;
+
+    pub fn encode(&self, buf: &mut impl BufMut) -> usize {
+        buf.put_u8(PING_PACKET_ID);
+        Self::SIZE
+    }
+}
@@ -0,0 +1,135 @@
+use crate::frame::{Frame, MAX_DATAGRAM};
+use crate::id::Id;
+use crate::position::Position;
+use tokio_util::bytes::BufMut;
+
+pub const SNAPSHOT_PACKET_ID: u8 = 3;
+
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct EntityState {
+    pub id: Id,
+    pub position: Position,
+}
+
+impl EntityState {
+    pub const SIZE: usize = size_of::<Id>() + size_of::<Position>();
+
+    pub fn encode(&self, buf: &mut impl BufMut) -> usize {
+        buf.put_slice(&self.id.as_bytes());
+        buf.put_slice(&self.position.to_be_bytes());
+        Self::SIZE
+    }
+
+    fn from_chunk(chunk: &[u8]) -> Self {
+        EntityState {
+            id: Id(u16::from_be_bytes([chunk[0], chunk[1]])),
+            position: Position::from_be_bytes(chunk[2..Self::SIZE].try_into().unwrap()),
+        }
+    }
+}
+
+/// State of the world as of simulation tick `tick`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Snapshot {
+    pub tick: u32,
+    pub players: Vec<EntityState>,
+    /// Everything in the world that is not a player. Its ids are not player ids.
+    pub entities: Vec<EntityState>,
+}
+
+impl Snapshot {
+    const HEADER_SIZE: usize = 9;
+    /// Most players a snapshot can hold and still fit in one datagram with its frame. Entities
+    /// get whatever room the players leave.
+    pub const MAX_PLAYERS: usize =
+        (MAX_DATAGRAM - Frame::HEADER_SIZE - Self::HEADER_SIZE) / EntityState::SIZE;
+
+    pub fn encoded_len(&self) -> usize {
+        Self::HEADER_SIZE + (self.players.len() + self.entities.len()) * EntityState::SIZE
+    }
+
+    pub fn encode(&self, buf: &mut impl BufMut) -> usize {
+        buf.put_u8(SNAPSHOT_PACKET_ID);
+        buf.put_u32(self.tick);
+        for states in [&self.players, &self.entities] {
+            buf.put_u16(states.len() as u16);
+            for state in states {
+                state.encode(buf);
+            }
+        }
+
+        self.encoded_len()
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut output = Vec::with_capacity(self.encoded_len());
+        self.encode(&mut output);
+        output
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Option<Snapshot> {
+        SnapshotRef::from_bytes(bytes).map(|snapshot| snapshot.to_owned())
+    }
+}
+
+/// A snapshot that has not been copied out of the datagram it arrived in.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct SnapshotRef<'a> {
+    pub tick: u32,
+    players: &'a [u8],
+    entities: &'a [u8],
+}
+
+impl<'a> SnapshotRef<'a> {
+    pub fn from_bytes(bytes: &'a [u8]) -> Option<SnapshotRef<'a>> {
+        let tick = u32::from_be_bytes(bytes.get(..4)?.try_into().unwrap());
+        let (players, rest) = states(&bytes[4..])?;
+        let (entities, rest) = states(rest)?;
+        if !rest.is_empty() {
+            return None;
+        }
+
+        Some(Self {
+            tick,
+            players,
+            entities,
+        })
+    }
+
+    /// Players in the snapshot.
+    pub fn len(&self) -> usize {
+        self.players.len() / EntityState::SIZE
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.players.is_empty()
+    }
+
+    pub fn players(&self) -> impl ExactSizeIterator<Item = EntityState> + 'a {
+        self.players
+            .chunks_exact(EntityState::SIZE)
+            .map(EntityState::from_chunk)
+    }
+
+    pub fn entities(&self) -> impl ExactSizeIterator<Item = EntityState> + 'a {
+        self.entities
+            .chunks_exact(EntityState::SIZE)
+            .map(EntityState::from_chunk)
+    }
+
+    pub fn to_owned(&self) -> Snapshot {
+        Snapshot {
+            tick: self.tick,
+            players: self.players().collect(),
+            entities: self.entities().collect(),
+        }
+    }
+}
+
+/// Splits a count and that many states off the front of `bytes`.
+fn states(bytes: &[u8]) -> Option<(&[u8], &[u8])> {
+    let count = u16::from_be_bytes(bytes.get(..2)?.try_into().unwrap());
+    let len = count as usize * EntityState::SIZE;
+    let rest = &bytes[2..];
+    (rest.len() >= len).then(|| rest.split_at(len))
+}
@@ -1,3 +1,4 @@
+use tokio_util::bytes::BufMut;
 use wasm_bindgen::prelude::wasm_bindgen;
 
 #[derive(Copy, Clone, Debug)]
@@ -11,3 +12,12 @@ impl Sync {
         vec![SYNC_PACKET_ID]
     }
 }
+
+impl Sync {
+    pub const SIZE: usize = 1;
+
+    pub fn encode(&self, buf: &mut impl BufMut) -> usize {
+        buf.put_u8(SYNC_PACKET_ID);
+        Self::SIZE
+    }
+}
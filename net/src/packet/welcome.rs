@@ -0,0 +1,56 @@
+use crate::id::Id;
+use tokio_util::bytes::BufMut;
+use wasm_bindgen::prelude::wasm_bindgen;
+
+pub const WELCOME_PACKET_ID: u8 = 5;
+
+/// The server's answer to a `Join`, telling the client which player it controls, how often the
+/// simulation ticks and how often it will be sent snapshots. `token` gets the same player back
+/// when joining again after losing the connection.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+#[wasm_bindgen]
+pub struct Welcome {
+    pub id: Id,
+    pub tick_rate: f32,
+    pub snapshot_rate: f32,
+    pub token: u64,
+}
+
+#[wasm_bindgen]
+impl Welcome {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut output = Vec::with_capacity(Self::SIZE);
+        self.encode(&mut output);
+        output
+    }
+}
+
+impl Welcome {
+    pub const SIZE: usize = 19;
+
+    pub fn encode(&self, buf: &mut impl BufMut) -> usize {
+        buf.put_u8(WELCOME_PACKET_ID);
+        buf.put_slice(&self.id.as_bytes());
+        buf.put_f32(self.tick_rate);
+        buf.put_f32(self.snapshot_rate);
+        buf.put_u64(self.token);
+        Self::SIZE
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Option<Welcome> {
+        if bytes.len() != Self::SIZE - 1 {
+            return None;
+        }
+
+        let id = Id(u16::from_be_bytes([bytes[0], bytes[1]]));
+        let tick_rate = f32::from_be_bytes([bytes[2], bytes[3], bytes[4], bytes[5]]);
+        let snapshot_rate = f32::from_be_bytes([bytes[6], bytes[7], bytes[8], bytes[9]]);
+        let token = u64::from_be_bytes(bytes[10..18].try_into().ok()?);
+        Some(Self {
+            id,
+            tick_rate,
+            snapshot_rate,
+            token,
+        })
+    }
+}
@@ -0,0 +1,45 @@
+use wasm_bindgen::prelude::wasm_bindgen;
+
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+#[wasm_bindgen]
+pub struct Position {
+    pub x: f32,
+    pub y: f32,
+}
+
+#[wasm_bindgen]
+impl Position {
+    #[wasm_bindgen(constructor)]
+    pub fn new(x: f32, y: f32) -> Position {
+        Position { x, y }
+    }
+
+    pub fn distance(&self, other: &Position) -> f32 {
+        let dx = other.x - self.x;
+        let dy = other.y - self.y;
+        (dx * dx + dy * dy).sqrt()
+    }
+
+    /// Linear interpolation towards `other`, where `t = 0.0` is `self` and `t = 1.0` is `other`.
+    /// Values of `t` outside of `0.0..=1.0` extrapolate along the same line.
+    pub fn lerp(&self, other: &Position, t: f32) -> Position {
+        Position {
+            x: self.x + (other.x - self.x) * t,
+            y: self.y + (other.y - self.y) * t,
+        }
+    }
+}
+
+impl Position {
+    pub fn to_be_bytes(&self) -> [u8; size_of::<Self>()] {
+        let x = f32::to_be_bytes(self.x);
+        let y = f32::to_be_bytes(self.y);
+        [x[0], x[1], x[2], x[3], y[0], y[1], y[2], y[3]]
+    }
+
+    pub fn from_be_bytes(bytes: [u8; size_of::<Self>()]) -> Self {
+        let x = f32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+        let y = f32::from_be_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]);
+        Self { x, y }
+    }
+}
@@ -4,9 +4,9 @@ use net::id::Id;
 
 #[derive(Copy, Clone, Debug, Default)]
 pub struct Entity {
-    id: Id,
-    position: Position,
-    velocity: Velocity,
+    pub id: Id,
+    pub position: Position,
+    pub velocity: Velocity,
 }
 
 impl Entity {
@@ -17,5 +17,5 @@ impl Entity {
         }
     }
 
-    pub fn tick(&mut self) {}
+    pub fn tick(&mut self, _dt: f32) {}
 }
@@ -0,0 +1,11 @@
+// Runs every tick, so anything worth saying goes through `tracing` where it can be filtered out.
+#![deny(clippy::print_stdout, clippy::print_stderr, clippy::dbg_macro)]
+
+pub mod entity;
+pub mod physics;
+pub mod player;
+pub mod simulation;
+pub mod state;
+pub mod tunables;
+
+pub const TPS: f32 = 20.0;
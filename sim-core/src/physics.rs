@@ -1,10 +1,13 @@
+use wasm_bindgen::prelude::wasm_bindgen;
+
 #[derive(Copy, Clone, Debug, Default)]
+#[wasm_bindgen]
 pub struct Velocity {
     pub x: f32,
     pub y: f32,
 
-    pub(crate) max_x: f32,
-    pub(crate) max_y: f32,
+    pub max_x: f32,
+    pub max_y: f32,
 }
 
 impl Velocity {
@@ -16,6 +19,7 @@ impl Velocity {
 }
 
 #[derive(Copy, Clone, Debug, Default)]
+#[wasm_bindgen]
 pub struct Acceleration {
     pub x: i8,
     pub y: i8,
@@ -1,7 +1,11 @@
-use crate::engine::TPS;
 use crate::physics::{Acceleration, Velocity};
+use crate::tunables::ACCELERATION;
 use net::id::Id;
+pub use net::position::Position;
+use wasm_bindgen::prelude::wasm_bindgen;
 
+#[derive(Copy, Clone, Debug, Default)]
+#[wasm_bindgen]
 pub struct KeyboardInput {
     pub up: bool,
     pub down: bool,
@@ -9,43 +13,41 @@ pub struct KeyboardInput {
     pub right: bool,
 }
 
-#[derive(Copy, Clone, Debug, Default)]
-pub struct Position {
-    pub x: f32,
-    pub y: f32,
-}
-
-impl Position {
-    pub fn to_be_bytes(&self) -> [u8; size_of::<Self>()] {
-        let x = f32::to_be_bytes(self.x);
-        let y = f32::to_be_bytes(self.y);
-        // [x[0], x[1], y[0], y[1]];
-        todo!()
-    }
-}
-
 #[derive(Debug, Default)]
+#[wasm_bindgen]
 pub struct Player {
     pub id: Id,
     pub position: Position,
     pub velocity: Velocity,
     pub acceleration: Acceleration,
+    /// Seconds since the last input, see `Tunables::expire_input`.
+    pub since_input: f32,
 }
 
+#[wasm_bindgen]
 impl Player {
-    pub fn new(id: Id) -> Self {
-        Self {
+    #[wasm_bindgen(constructor)]
+    pub fn new(id: Id) -> Player {
+        Player {
             id,
             ..Default::default()
         }
     }
 
-    pub fn tick(&mut self) {
-        println!("[ENGINE]: {:#?}", self);
-        self.update_position();
+    /// Advances the player by `dt` seconds.
+    pub fn tick(&mut self, dt: f32) {
+        self.update_position(dt);
     }
 
     pub fn input(&mut self, keyboard_input: KeyboardInput) {
+        self.accelerate(keyboard_input, ACCELERATION);
+    }
+}
+
+impl Player {
+    /// Like `input`, with `acceleration` for each key held instead of the default. Every input
+    /// describes all keys, so keys it does not hold are released.
+    pub fn accelerate(&mut self, keyboard_input: KeyboardInput, acceleration: i8) {
         let KeyboardInput {
             up,
             down,
@@ -55,39 +57,47 @@ impl Player {
 
         self.acceleration.x = 0;
         self.acceleration.y = 0;
+        self.since_input = 0.0;
 
         if up {
-            self.acceleration.y += 10;
+            self.acceleration.y += acceleration;
         }
 
         if down {
-            self.acceleration.y -= 10;
+            self.acceleration.y -= acceleration;
         }
 
         if left {
-            self.acceleration.x -= 10;
+            self.acceleration.x -= acceleration;
         }
 
         if right {
-            self.acceleration.x += 10;
+            self.acceleration.x += acceleration;
         }
     }
 
-    fn update_position(&mut self) {
+    /// Brings the player to rest where it is, with no keys held.
+    pub fn stop(&mut self) {
+        self.acceleration = Acceleration::default();
+        self.velocity.x = 0.0;
+        self.velocity.y = 0.0;
+    }
+
+    fn update_position(&mut self, dt: f32) {
         // if acceleration isn't 0, then friction comes into play
 
-        self.velocity = self.calculate_velocity();
-        self.position = self.calculate_position();
+        self.velocity = self.calculate_velocity_with_dt(dt);
+        self.position = self.calculate_position_with_dt(dt);
     }
 
-    pub fn calculate_velocity(&self) -> Velocity {
-        self.calculate_velocity_with_tps(TPS)
+    pub fn calculate_velocity_with_tps(&self, tps: f32) -> Velocity {
+        self.calculate_velocity_with_dt(1.0 / tps)
     }
 
-    pub fn calculate_velocity_with_tps(&self, tps: f32) -> Velocity {
+    pub fn calculate_velocity_with_dt(&self, dt: f32) -> Velocity {
         let acceleration = self.acceleration;
-        let dx = acceleration.x as f32 * (1.0 / tps);
-        let dy = acceleration.y as f32 * (1.0 / tps);
+        let dx = acceleration.x as f32 * dt;
+        let dy = acceleration.y as f32 * dt;
 
         let mut velocity = self.velocity;
         increase_velocity(&mut velocity, dx, dy);
@@ -95,14 +105,14 @@ impl Player {
         velocity
     }
 
-    pub fn calculate_position(&self) -> Position {
-        self.calculate_position_with_tps(TPS)
+    pub fn calculate_position_with_tps(&self, tps: f32) -> Position {
+        self.calculate_position_with_dt(1.0 / tps)
     }
 
-    pub fn calculate_position_with_tps(&self, tps: f32) -> Position {
+    pub fn calculate_position_with_dt(&self, dt: f32) -> Position {
         let velocity = self.velocity;
-        let distance_x = velocity.x * (1.0 / tps);
-        let distance_y = velocity.y * (1.0 / tps);
+        let distance_x = velocity.x * dt;
+        let distance_y = velocity.y * dt;
 
         let x = self.position.x + distance_x;
         let y = self.position.y + distance_y;
@@ -110,7 +120,7 @@ impl Player {
         Position { x, y }
     }
 
-    fn to_be_bytes(
+    pub fn to_be_bytes(
         &self,
     ) -> [u8; size_of::<Id>() + size_of::<Position>() + (size_of::<Velocity>() / 2)] {
         let id = self.id.as_bytes();
@@ -123,6 +133,10 @@ impl Player {
             position[1],
             position[2],
             position[3],
+            position[4],
+            position[5],
+            position[6],
+            position[7],
             velocity[0],
             velocity[1],
             velocity[2],
@@ -131,12 +145,11 @@ impl Player {
             velocity[5],
             velocity[6],
             velocity[7],
-        ];
-        todo!()
+        ]
     }
 }
 
-fn increase_velocity(velocity: &mut Velocity, x: f32, y: f32) {
+pub fn increase_velocity(velocity: &mut Velocity, x: f32, y: f32) {
     if x > 0.0 {
         velocity.x = (velocity.x + x).min(velocity.max_x);
     } else {
@@ -150,7 +163,7 @@ fn increase_velocity(velocity: &mut Velocity, x: f32, y: f32) {
     }
 }
 
-fn decrease_velocity(velocity: &mut Velocity, x: f32, y: f32) {
+pub fn decrease_velocity(velocity: &mut Velocity, x: f32, y: f32) {
     if x > 0.0 {
         velocity.x = (velocity.x - x).max(0.0);
     } else {
@@ -198,6 +211,7 @@ mod tests {
                     ..Default::default()
                 },
                 acceleration: Acceleration { x: 5, y: 5 },
+                since_input: 0.0,
             };
 
             assert_eq!(
@@ -241,7 +255,7 @@ mod tests {
             assert_eq!(size, 8);
 
             let size = size_of::<Player>();
-            assert_eq!(size, 40);
+            assert_eq!(size, 32);
         }
     }
     mod behavior {
@@ -258,6 +272,7 @@ mod tests {
                     ..Default::default()
                 },
                 acceleration: Default::default(),
+                since_input: 0.0,
             };
 
             let tps = 20.0;
@@ -296,7 +311,7 @@ mod tests {
                 },
                 ..Default::default()
             };
-            let pos = player.calculate_position();
+            let pos = player.calculate_position_with_tps(20.0);
             assert_eq!(pos.x, -0.5);
             assert_eq!(pos.y, 0.25);
         }
@@ -312,7 +327,7 @@ mod tests {
                 },
                 ..Default::default()
             };
-            let pos = player.calculate_position();
+            let pos = player.calculate_position_with_tps(20.0);
             assert_eq!(pos.x, 2.5);
             assert_eq!(pos.y, 0.0);
         }
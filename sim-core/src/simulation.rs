@@ -0,0 +1,97 @@
+use crate::player::{KeyboardInput, Position};
+use crate::state::{self, Input, State};
+use net::id::Id;
+use wasm_bindgen::prelude::wasm_bindgen;
+
+/// `State` and `step` for callers across the wasm boundary, which cannot hold a `State` or pass
+/// a slice of inputs. Inputs are queued with `push_input` and applied by the next `step`.
+#[derive(Debug, Default)]
+#[wasm_bindgen]
+pub struct Simulation {
+    state: State,
+    inputs: Vec<Input>,
+}
+
+#[wasm_bindgen]
+impl Simulation {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> Simulation {
+        Simulation::default()
+    }
+
+    pub fn spawn(&mut self, id: Id) {
+        self.state.spawn(id);
+    }
+
+    pub fn remove(&mut self, id: Id) {
+        self.state.players.remove(&id);
+    }
+
+    pub fn push_input(&mut self, id: Id, keyboard: KeyboardInput) {
+        self.inputs.push(Input { id, keyboard });
+    }
+
+    /// Advances the simulation by `dt` seconds with every input pushed since the last step.
+    pub fn step(&mut self, dt: f32) {
+        state::step(&mut self.state, &self.inputs, dt);
+        self.inputs.clear();
+    }
+
+    pub fn position(&self, id: Id) -> Option<Position> {
+        self.state.players.get(&id).map(|player| player.position)
+    }
+}
+
+impl Simulation {
+    pub fn from_state(state: State) -> Self {
+        Simulation {
+            state,
+            inputs: Vec::new(),
+        }
+    }
+
+    pub fn state(&self) -> &State {
+        &self.state
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    mod behavior {
+        use crate::player::KeyboardInput;
+        use crate::simulation::Simulation;
+        use net::id::Id;
+
+        #[test]
+        fn step_applies_pushed_inputs_once() {
+            let id = Id(1);
+            let mut simulation = Simulation::new();
+            simulation.spawn(id);
+            let start = simulation.position(id).unwrap();
+
+            simulation.push_input(
+                id,
+                KeyboardInput {
+                    right: true,
+                    ..Default::default()
+                },
+            );
+            simulation.step(0.5);
+            let moved = simulation.position(id).unwrap();
+            assert!(moved.x > start.x);
+            assert_eq!(simulation.state().players[&id].acceleration.x, 10);
+
+            simulation.step(0.0);
+            assert!(simulation.inputs.is_empty());
+        }
+
+        #[test]
+        fn removed_players_have_no_position() {
+            let id = Id(1);
+            let mut simulation = Simulation::new();
+            simulation.spawn(id);
+            simulation.remove(id);
+            assert!(simulation.position(id).is_none());
+        }
+    }
+}
@@ -0,0 +1,147 @@
+use crate::entity::Entity;
+use crate::player::{KeyboardInput, Player};
+use crate::tunables::Tunables;
+use net::id::Id;
+use std::collections::HashMap;
+use tracing::{trace, trace_span};
+
+#[derive(Debug, Default)]
+pub struct State {
+    pub players: HashMap<Id, Player>,
+    pub entities: Vec<Entity>,
+    pub tunables: Tunables,
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct Input {
+    pub id: Id,
+    pub keyboard: KeyboardInput,
+}
+
+impl State {
+    /// Adds a player where the spawn rules say it should appear, replacing any player with the
+    /// same id.
+    pub fn spawn(&mut self, id: Id) -> &mut Player {
+        let mut player = Player::new(id);
+        player.position = self.tunables.spawn_position(id);
+        player.velocity.max_x = self.tunables.max_velocity;
+        player.velocity.max_y = self.tunables.max_velocity;
+        self.players.insert(id, player);
+        self.players.get_mut(&id).unwrap()
+    }
+}
+
+/// Advances the whole simulation by `dt` seconds, applying `inputs` before anything moves.
+/// Inputs for players that are not in `state` are ignored.
+pub fn step(state: &mut State, inputs: &[Input], dt: f32) {
+    apply_inputs(state, inputs);
+    tick_players(state, dt);
+    tick_entities(state, dt);
+}
+
+/// The parts of `step`, for callers that want to time them separately.
+pub fn apply_inputs(state: &mut State, inputs: &[Input]) {
+    for input in inputs {
+        if let Some(player) = state.players.get_mut(&input.id) {
+            player.accelerate(input.keyboard, state.tunables.acceleration);
+        }
+    }
+}
+
+pub fn tick_players(state: &mut State, dt: f32) {
+    let tunables = state.tunables;
+    for (id, player) in state.players.iter_mut() {
+        let _span = trace_span!("player", id = id.0).entered();
+        player.velocity.max_x = tunables.max_velocity;
+        player.velocity.max_y = tunables.max_velocity;
+        tunables.expire_input(player, dt);
+        tunables.apply_friction(player, dt);
+        player.tick(dt);
+        tunables.contain(player);
+        trace!(position = ?player.position, velocity = ?player.velocity);
+    }
+}
+
+pub fn tick_entities(state: &mut State, dt: f32) {
+    for entity in state.entities.iter_mut() {
+        entity.tick(dt);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    mod behavior {
+        use crate::player::{KeyboardInput, Player};
+        use crate::state::{step, Input, State};
+        use crate::tunables::Tunables;
+        use net::id::Id;
+
+        #[test]
+        fn step_applies_inputs_then_moves() {
+            let id = Id(1);
+            let mut state = State::default();
+            let mut player = Player::new(id);
+            player.velocity.max_x = 10.0;
+            player.velocity.max_y = 10.0;
+            state.players.insert(id, player);
+
+            let input = Input {
+                id,
+                keyboard: KeyboardInput {
+                    right: true,
+                    ..Default::default()
+                },
+            };
+            step(&mut state, &[input], 0.5);
+
+            let player = &state.players[&id];
+            assert_eq!(player.acceleration.x, 10);
+            assert_eq!(player.velocity.x, 5.0);
+            assert_eq!(player.position.x, 2.5);
+        }
+
+        #[test]
+        fn step_uses_tunables() {
+            let id = Id(1);
+            let mut state = State {
+                tunables: Tunables {
+                    acceleration: 20,
+                    max_velocity: 4.0,
+                    world_width: 4.0,
+                    ..Default::default()
+                },
+                ..Default::default()
+            };
+            state.players.insert(id, Player::new(id));
+
+            let input = Input {
+                id,
+                keyboard: KeyboardInput {
+                    right: true,
+                    ..Default::default()
+                },
+            };
+            step(&mut state, &[input], 0.5);
+            let player = &state.players[&id];
+            assert_eq!(player.acceleration.x, 20);
+            assert_eq!(player.velocity.x, 4.0);
+            assert_eq!(player.position.x, 2.0);
+
+            step(&mut state, &[], 0.5);
+            let player = &state.players[&id];
+            assert_eq!(player.position.x, 2.0);
+            assert_eq!(player.velocity.x, 0.0);
+        }
+
+        #[test]
+        fn step_ignores_unknown_players() {
+            let mut state = State::default();
+            let input = Input {
+                id: Id(7),
+                keyboard: Default::default(),
+            };
+            step(&mut state, &[input], 0.05);
+            assert!(state.players.is_empty());
+        }
+    }
+}
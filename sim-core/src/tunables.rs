@@ -0,0 +1,182 @@
+use crate::physics::Acceleration;
+use crate::player::{Player, Position};
+use net::id::Id;
+
+/// Acceleration a held key gives a player, per second.
+pub const ACCELERATION: i8 = 10;
+pub const MAX_VELOCITY: f32 = 10.0;
+/// Seconds without input after which a player's keys count as released. Clients send their input
+/// many times a second, so this only runs out when several in a row are lost or a client stops.
+pub const INPUT_TIMEOUT: f32 = 0.5;
+pub const WORLD_WIDTH: f32 = 1000.0;
+pub const WORLD_HEIGHT: f32 = 1000.0;
+
+/// Angle between players spawned one after another, spreads them evenly over the spawn circle.
+const GOLDEN_ANGLE: f32 = 2.399_963;
+
+/// Gameplay parameters the simulation reads every tick, rather than baking them into players.
+/// Changing them takes effect on the next tick.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Tunables {
+    pub acceleration: i8,
+    pub max_velocity: f32,
+    /// Slowdown per second along an axis no key is held for.
+    pub friction: f32,
+    /// The world is centred on the origin, players can not leave it.
+    pub world_width: f32,
+    pub world_height: f32,
+    /// New players appear on a circle of `spawn_radius` around the spawn point.
+    pub spawn_x: f32,
+    pub spawn_y: f32,
+    pub spawn_radius: f32,
+    pub input_timeout: f32,
+}
+
+impl Tunables {
+    /// Keeps `player` inside the world, stopping it along the axis it ran into a wall on.
+    pub fn contain(&self, player: &mut Player) {
+        let Position { x, y } = player.position;
+        let (half_width, half_height) = (self.world_width / 2.0, self.world_height / 2.0);
+
+        player.position.x = x.clamp(-half_width, half_width);
+        if player.position.x != x {
+            player.velocity.x = 0.0;
+        }
+
+        player.position.y = y.clamp(-half_height, half_height);
+        if player.position.y != y {
+            player.velocity.y = 0.0;
+        }
+    }
+
+    /// Slows `player` down over `dt` seconds along every axis it is not accelerating on.
+    pub fn apply_friction(&self, player: &mut Player, dt: f32) {
+        let slowdown = self.friction * dt;
+        if player.acceleration.x == 0 {
+            player.velocity.x = towards_zero(player.velocity.x, slowdown);
+        }
+        if player.acceleration.y == 0 {
+            player.velocity.y = towards_zero(player.velocity.y, slowdown);
+        }
+    }
+
+    /// Releases every key of `player` once it has gone longer than `input_timeout` without input,
+    /// counting `dt` more seconds, so a client that stops sending does not keep accelerating.
+    pub fn expire_input(&self, player: &mut Player, dt: f32) {
+        player.since_input += dt;
+        if player.since_input > self.input_timeout {
+            player.acceleration = Acceleration::default();
+        }
+    }
+
+    /// Where the player with `id` appears, always the same place for the same id.
+    pub fn spawn_position(&self, id: Id) -> Position {
+        let angle = id.0 as f32 * GOLDEN_ANGLE;
+        Position {
+            x: self.spawn_x + self.spawn_radius * angle.cos(),
+            y: self.spawn_y + self.spawn_radius * angle.sin(),
+        }
+    }
+}
+
+fn towards_zero(velocity: f32, by: f32) -> f32 {
+    if velocity > 0.0 {
+        (velocity - by).max(0.0)
+    } else {
+        (velocity + by).min(0.0)
+    }
+}
+
+impl Default for Tunables {
+    fn default() -> Self {
+        Self {
+            acceleration: ACCELERATION,
+            max_velocity: MAX_VELOCITY,
+            friction: 0.0,
+            world_width: WORLD_WIDTH,
+            world_height: WORLD_HEIGHT,
+            spawn_x: 0.0,
+            spawn_y: 0.0,
+            spawn_radius: 0.0,
+            input_timeout: INPUT_TIMEOUT,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    mod behavior {
+        use crate::physics::Acceleration;
+        use crate::player::{KeyboardInput, Player};
+        use crate::tunables::Tunables;
+        use net::id::Id;
+
+        #[test]
+        fn friction_only_slows_idle_axes() {
+            let tunables = Tunables {
+                friction: 4.0,
+                ..Default::default()
+            };
+            let mut player = Player::new(Id(1));
+            player.velocity.x = 3.0;
+            player.velocity.y = -1.0;
+            player.acceleration = Acceleration { x: 10, y: 0 };
+
+            tunables.apply_friction(&mut player, 0.5);
+            assert_eq!(player.velocity.x, 3.0);
+            assert_eq!(player.velocity.y, 0.0);
+
+            player.acceleration.x = 0;
+            tunables.apply_friction(&mut player, 0.25);
+            assert_eq!(player.velocity.x, 2.0);
+        }
+
+        #[test]
+        fn input_expires_without_new_input() {
+            let tunables = Tunables {
+                input_timeout: 0.5,
+                ..Default::default()
+            };
+            let mut player = Player::new(Id(1));
+            let keys = KeyboardInput {
+                up: true,
+                right: true,
+                ..Default::default()
+            };
+
+            player.accelerate(keys, 10);
+            tunables.expire_input(&mut player, 0.25);
+            assert_eq!(player.acceleration.x, 10);
+
+            // New input restarts the timeout.
+            player.accelerate(keys, 10);
+            tunables.expire_input(&mut player, 0.25);
+            tunables.expire_input(&mut player, 0.2);
+            assert_eq!(player.acceleration.y, 10);
+
+            tunables.expire_input(&mut player, 0.1);
+            assert_eq!(player.acceleration.x, 0);
+            assert_eq!(player.acceleration.y, 0);
+        }
+
+        #[test]
+        fn spawn_positions_are_on_the_circle() {
+            let tunables = Tunables {
+                spawn_x: 10.0,
+                spawn_y: -5.0,
+                spawn_radius: 2.0,
+                ..Default::default()
+            };
+            for id in 0..8 {
+                let position = tunables.spawn_position(Id(id));
+                let distance = ((position.x - 10.0).powi(2) + (position.y + 5.0).powi(2)).sqrt();
+                assert!((distance - 2.0).abs() < 1e-4, "{position:?}");
+                assert_eq!(position, tunables.spawn_position(Id(id)));
+            }
+            assert_ne!(
+                tunables.spawn_position(Id(0)),
+                tunables.spawn_position(Id(1))
+            );
+        }
+    }
+}
@@ -0,0 +1,92 @@
+use crate::config::parse_tps;
+use sim_core::tunables::Tunables;
+
+/// Commands typed into the server console. Engine commands are applied at the next tick
+/// boundary.
+#[derive(Clone, Debug, PartialEq)]
+pub enum AdminCommand {
+    SetTps(f32),
+    /// Replaces the log filter, e.g. `info,lib::server=debug`.
+    SetLogFilter(String),
+    /// Reads the config file again and applies its gameplay tunables.
+    Reload,
+    /// Sent by the config watcher rather than typed in.
+    SetTunables(Tunables),
+}
+
+impl AdminCommand {
+    pub fn parse(line: &str) -> Result<Self, String> {
+        let mut words = line.split_whitespace();
+        let command = words.next().unwrap_or_default();
+        match command {
+            "tps" => {
+                let Some(value) = words.next() else {
+                    return Err("Usage: tps <ticks per second>".to_string());
+                };
+                // Same range as the config file, anything else would stall or crash the engine.
+                parse_tps(value)
+                    .map(AdminCommand::SetTps)
+                    .map_err(|e| format!("Invalid tick rate: {e}"))
+            }
+            "log" => {
+                let filter = words.collect::<Vec<_>>().join(",");
+                if filter.is_empty() {
+                    return Err("Usage: log <filter>".to_string());
+                }
+                Ok(AdminCommand::SetLogFilter(filter))
+            }
+            "reload" => Ok(AdminCommand::Reload),
+            _ => Err(format!("Unknown command: {command}")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    mod parse {
+        use crate::admin::AdminCommand;
+
+        #[test]
+        fn set_tps() {
+            assert_eq!(
+                AdminCommand::parse("tps 60"),
+                Ok(AdminCommand::SetTps(60.0))
+            );
+            assert_eq!(
+                AdminCommand::parse("  tps   30.5 "),
+                Ok(AdminCommand::SetTps(30.5))
+            );
+        }
+
+        #[test]
+        fn set_log_filter() {
+            assert_eq!(
+                AdminCommand::parse("log debug"),
+                Ok(AdminCommand::SetLogFilter("debug".to_string()))
+            );
+            assert_eq!(
+                AdminCommand::parse("log info lib::server=trace"),
+                Ok(AdminCommand::SetLogFilter(
+                    "info,lib::server=trace".to_string()
+                ))
+            );
+            assert!(AdminCommand::parse("log").is_err());
+        }
+
+        #[test]
+        fn reload() {
+            assert_eq!(AdminCommand::parse("reload"), Ok(AdminCommand::Reload));
+        }
+
+        #[test]
+        fn rejects_bad_input() {
+            assert!(AdminCommand::parse("tps").is_err());
+            assert!(AdminCommand::parse("tps 0").is_err());
+            assert!(AdminCommand::parse("tps fast").is_err());
+            assert!(AdminCommand::parse("tps 1e10").is_err());
+            assert!(AdminCommand::parse("tps 1e-30").is_err());
+            assert!(AdminCommand::parse("tps NaN").is_err());
+            assert!(AdminCommand::parse("teleport").is_err());
+        }
+    }
+}
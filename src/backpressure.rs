@@ -0,0 +1,334 @@
+use crate::engine::Message;
+use net::packet::Packet;
+use std::collections::VecDeque;
+use std::fmt::{Display, Formatter};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use tokio::sync::mpsc::error::TrySendError;
+use tokio::sync::mpsc::Sender;
+
+/// Messages a single connection may have waiting for the engine before the overload policy kicks
+/// in.
+pub const CONNECTION_QUOTA: usize = 16;
+
+/// What to give up when a connection's outbox is full.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub enum OverloadPolicy {
+    /// Keep the newest packet and drop the one that has been waiting the longest.
+    #[default]
+    DropOldest,
+    /// Keep what is already queued and drop the packet that just arrived.
+    DropNewest,
+}
+
+#[derive(Debug, Default)]
+pub struct OverloadStats {
+    pub dropped_oldest: AtomicU64,
+    pub dropped_newest: AtomicU64,
+    /// Movement inputs replaced by a newer one from the same player before the engine saw them.
+    pub coalesced: AtomicU64,
+}
+
+impl OverloadStats {
+    pub fn dropped(&self) -> u64 {
+        self.dropped_oldest.load(Ordering::Relaxed) + self.dropped_newest.load(Ordering::Relaxed)
+    }
+
+    pub fn coalesced(&self) -> u64 {
+        self.coalesced.load(Ordering::Relaxed)
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct EngineClosed;
+
+impl Display for EngineClosed {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "engine is no longer accepting packets")
+    }
+}
+
+impl std::error::Error for EngineClosed {}
+
+/// Messages from one connection that the engine has not taken yet, in the order they arrived.
+/// Nothing here ever waits on the engine: messages are handed over with `try_send` and whatever
+/// does not fit stays queued, up to `quota` packets. Joins, leaves and suspends are not packets,
+/// do not count towards the quota and are never dropped.
+#[derive(Debug)]
+pub struct Outbox {
+    queue: VecDeque<Message>,
+    /// How many of `queue` are packets. Losing a join, leave or suspend would leave a player the
+    /// server welcomed unspawned, or one it forgot in the world, so only packets are counted.
+    packets: usize,
+    quota: usize,
+    policy: OverloadPolicy,
+    stats: Arc<OverloadStats>,
+}
+
+impl Outbox {
+    pub fn new(quota: usize, policy: OverloadPolicy, stats: Arc<OverloadStats>) -> Self {
+        Self {
+            queue: VecDeque::with_capacity(quota),
+            packets: 0,
+            quota: quota.max(1),
+            policy,
+            stats,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.queue.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.queue.is_empty()
+    }
+
+    pub fn push(&mut self, message: impl Into<Message>) {
+        let message = message.into();
+        if !matches!(message, Message::Packet(_)) {
+            self.queue.push_back(message);
+            return;
+        }
+        if let Message::Packet(Packet::Movement(movement)) = &message {
+            // Only movement since the last join, leave or suspend, which must not be reordered
+            // with what came before them.
+            let pending = self
+                .queue
+                .iter_mut()
+                .rev()
+                .take_while(|queued| matches!(queued, Message::Packet(_)))
+                .find_map(|queued| match queued {
+                    Message::Packet(Packet::Movement(queued)) if queued.id == movement.id => {
+                        Some(queued)
+                    }
+                    _ => None,
+                });
+            if let Some(pending) = pending {
+                *pending = *movement;
+                self.stats.coalesced.fetch_add(1, Ordering::Relaxed);
+                return;
+            }
+        }
+
+        if self.packets >= self.quota {
+            match self.policy {
+                OverloadPolicy::DropOldest => {
+                    let oldest = self
+                        .queue
+                        .iter()
+                        .position(|queued| matches!(queued, Message::Packet(_)));
+                    if let Some(oldest) = oldest {
+                        self.queue.remove(oldest);
+                        self.packets -= 1;
+                    }
+                    self.stats.dropped_oldest.fetch_add(1, Ordering::Relaxed);
+                }
+                OverloadPolicy::DropNewest => {
+                    self.stats.dropped_newest.fetch_add(1, Ordering::Relaxed);
+                    return;
+                }
+            }
+        }
+
+        self.queue.push_back(message);
+        self.packets += 1;
+    }
+
+    /// Hands as many queued messages to the engine as it has room for.
+    pub fn flush(&mut self, tx: &Sender<Message>) -> Result<(), EngineClosed> {
+        while let Some(message) = self.queue.pop_front() {
+            let is_packet = matches!(message, Message::Packet(_));
+            match tx.try_send(message) {
+                Ok(()) => {
+                    if is_packet {
+                        self.packets -= 1;
+                    }
+                }
+                Err(TrySendError::Full(message)) => {
+                    self.queue.push_front(message);
+                    break;
+                }
+                Err(TrySendError::Closed(_)) => {
+                    self.queue.clear();
+                    self.packets = 0;
+                    return Err(EngineClosed);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Hands over everything still queued, waiting for the engine to make room. Only for when the
+    /// connection is going away and nothing else is waiting on the server.
+    pub async fn close(&mut self, tx: &Sender<Message>) -> Result<(), EngineClosed> {
+        self.packets = 0;
+        // Whatever is left when the engine has gone is dropped along with the drain.
+        for message in self.queue.drain(..) {
+            if tx.send(message).await.is_err() {
+                return Err(EngineClosed);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    mod behavior {
+        use crate::backpressure::{EngineClosed, Outbox, OverloadPolicy, OverloadStats};
+        use crate::engine::Message;
+        use net::id::Id;
+        use net::packet::movement::Movement;
+        use net::packet::ping::Ping;
+        use net::packet::Packet;
+        use std::sync::Arc;
+        use tokio::sync::mpsc::channel;
+
+        fn movement(id: u16, up: bool) -> Packet {
+            Packet::Movement(Movement {
+                id: Id(id),
+                up,
+                ..Default::default()
+            })
+        }
+
+        #[test]
+        fn coalesces_movement_from_the_same_player() {
+            let stats = Arc::new(OverloadStats::default());
+            let mut outbox = Outbox::new(4, OverloadPolicy::DropOldest, stats.clone());
+            outbox.push(movement(1, false));
+            outbox.push(movement(2, false));
+            outbox.push(movement(1, true));
+
+            assert_eq!(outbox.len(), 2);
+            assert_eq!(stats.coalesced(), 1);
+
+            let (tx, mut rx) = channel(4);
+            outbox.flush(&tx).unwrap();
+            let Some(Message::Packet(Packet::Movement(first))) = rx.try_recv().ok() else {
+                panic!("Expected a movement");
+            };
+            assert_eq!(first.id, Id(1));
+            assert!(first.up);
+        }
+
+        #[test]
+        fn drop_oldest_keeps_latest_packets() {
+            let stats = Arc::new(OverloadStats::default());
+            let mut outbox = Outbox::new(2, OverloadPolicy::DropOldest, stats.clone());
+            for id in 0..3 {
+                outbox.push(movement(id, false));
+            }
+
+            assert_eq!(outbox.len(), 2);
+            assert_eq!(stats.dropped(), 1);
+
+            let (tx, mut rx) = channel(4);
+            outbox.flush(&tx).unwrap();
+            let Some(Message::Packet(Packet::Movement(first))) = rx.try_recv().ok() else {
+                panic!("Expected a movement");
+            };
+            assert_eq!(first.id, Id(1));
+        }
+
+        #[test]
+        fn drop_newest_keeps_queued_packets() {
+            let stats = Arc::new(OverloadStats::default());
+            let mut outbox = Outbox::new(2, OverloadPolicy::DropNewest, stats.clone());
+            for id in 0..3 {
+                outbox.push(movement(id, false));
+            }
+
+            let (tx, mut rx) = channel(4);
+            outbox.flush(&tx).unwrap();
+            assert_eq!(stats.dropped(), 1);
+            assert_eq!(rx.len(), 2);
+            let Some(Message::Packet(Packet::Movement(first))) = rx.try_recv().ok() else {
+                panic!("Expected a movement");
+            };
+            assert_eq!(first.id, Id(0));
+        }
+
+        #[test]
+        fn control_messages_are_never_dropped() {
+            for policy in [OverloadPolicy::DropOldest, OverloadPolicy::DropNewest] {
+                let stats = Arc::new(OverloadStats::default());
+                let mut outbox = Outbox::new(2, policy, stats.clone());
+                outbox.push(Message::Join {
+                    id: Id(9),
+                    nickname: Default::default(),
+                });
+                for id in 0..4 {
+                    outbox.push(movement(id, false));
+                }
+                outbox.push(Message::Leave(Id(9)));
+
+                assert_eq!(stats.dropped(), 2, "{policy:?}");
+                assert_eq!(outbox.len(), 4, "{policy:?}");
+
+                let (tx, mut rx) = channel(4);
+                outbox.flush(&tx).unwrap();
+                assert!(matches!(rx.try_recv(), Ok(Message::Join { id: Id(9), .. })));
+                assert!(matches!(rx.try_recv(), Ok(Message::Packet(_))));
+                assert!(matches!(rx.try_recv(), Ok(Message::Packet(_))));
+                assert!(matches!(rx.try_recv(), Ok(Message::Leave(Id(9)))));
+            }
+        }
+
+        #[test]
+        fn movement_is_not_moved_past_a_suspend() {
+            let stats = Arc::new(OverloadStats::default());
+            let mut outbox = Outbox::new(4, OverloadPolicy::DropOldest, stats.clone());
+            outbox.push(movement(1, true));
+            outbox.push(Message::Suspend(Id(1)));
+            outbox.push(movement(1, false));
+
+            assert_eq!(outbox.len(), 3);
+            assert_eq!(stats.coalesced(), 0);
+
+            let (tx, mut rx) = channel(4);
+            outbox.flush(&tx).unwrap();
+            assert!(matches!(
+                rx.try_recv(),
+                Ok(Message::Packet(Packet::Movement(Movement { up: true, .. })))
+            ));
+            assert!(matches!(rx.try_recv(), Ok(Message::Suspend(Id(1)))));
+            assert!(matches!(
+                rx.try_recv(),
+                Ok(Message::Packet(Packet::Movement(Movement {
+                    up: false,
+                    ..
+                })))
+            ));
+        }
+
+        #[test]
+        fn flush_keeps_what_does_not_fit() {
+            let stats = Arc::new(OverloadStats::default());
+            let mut outbox = Outbox::new(4, OverloadPolicy::DropOldest, stats);
+            for _ in 0..3 {
+                outbox.push(Packet::Ping(Ping));
+            }
+
+            let (tx, mut rx) = channel(2);
+            outbox.flush(&tx).unwrap();
+            assert_eq!(outbox.len(), 1);
+
+            rx.try_recv().unwrap();
+            outbox.flush(&tx).unwrap();
+            assert!(outbox.is_empty());
+        }
+
+        #[test]
+        fn flush_reports_closed_engine() {
+            let stats = Arc::new(OverloadStats::default());
+            let mut outbox = Outbox::new(4, OverloadPolicy::DropOldest, stats);
+            outbox.push(Packet::Ping(Ping));
+
+            let (tx, rx) = channel(2);
+            drop(rx);
+            assert_eq!(outbox.flush(&tx), Err(EngineClosed));
+        }
+    }
+}
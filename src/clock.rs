@@ -0,0 +1,84 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Source of time for the engine loop, so that it can be driven by something other than the wall
+/// clock.
+pub trait Clock: Send {
+    fn now(&self) -> Instant;
+}
+
+#[derive(Copy, Clone, Debug, Default)]
+pub struct RealClock;
+
+impl Clock for RealClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+}
+
+/// A clock that only moves when told to. Clones share the same time, so a test can keep one
+/// handle and give the other to the engine.
+#[derive(Clone, Debug)]
+pub struct ManualClock {
+    start: Instant,
+    elapsed_nanos: Arc<AtomicU64>,
+}
+
+impl ManualClock {
+    pub fn new() -> Self {
+        Self {
+            start: Instant::now(),
+            elapsed_nanos: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    pub fn advance(&self, duration: Duration) {
+        self.elapsed_nanos
+            .fetch_add(duration.as_nanos() as u64, Ordering::SeqCst);
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        Duration::from_nanos(self.elapsed_nanos.load(Ordering::SeqCst))
+    }
+}
+
+impl Default for ManualClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> Instant {
+        self.start + self.elapsed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    mod behavior {
+        use crate::clock::{Clock, ManualClock};
+        use std::time::Duration;
+
+        #[test]
+        fn manual_clock_only_moves_when_advanced() {
+            let clock = ManualClock::new();
+            let start = clock.now();
+            assert_eq!(clock.now(), start);
+
+            clock.advance(Duration::from_millis(50));
+            assert_eq!(clock.now() - start, Duration::from_millis(50));
+        }
+
+        #[test]
+        fn manual_clock_clones_share_time() {
+            let clock = ManualClock::new();
+            let handle = clock.clone();
+            let start = clock.now();
+
+            handle.advance(Duration::from_secs(1));
+            assert_eq!(clock.now() - start, Duration::from_secs(1));
+        }
+    }
+}
@@ -1,20 +1,593 @@
+use crate::backpressure::{OverloadPolicy, CONNECTION_QUOTA};
+use crate::engine::TPS;
+use crate::logging::DEFAULT_FILTER;
+use net::packet::snapshot::Snapshot;
+use sim_core::tunables::Tunables;
+use std::fmt::{Display, Formatter};
 use std::net::SocketAddr;
+use std::ops::RangeInclusive;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
+use tracing_subscriber::EnvFilter;
 
-#[derive(Clone, Copy, Debug)]
+pub const SNAPSHOT_RATE: f32 = 20.0;
+pub const ENGINE_QUEUE_CAPACITY: usize = 1024;
+pub const MAX_PLAYERS: usize = 64;
+/// Peers that have not sent anything for this long are forgotten.
+pub const CONNECTION_TIMEOUT: Duration = Duration::from_secs(10);
+/// How long a player whose connection was lost is kept for the client to come back.
+pub const RECONNECT_GRACE: Duration = Duration::from_secs(15);
+/// Longest a shutdown may take before the process exits anyway.
+pub const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Tick rates the engine can keep, from the config or the admin console. Slower ticks are too
+/// long to be useful and faster ones too short to time.
+pub const TPS_RANGE: RangeInclusive<f32> = 1.0..=1000.0;
+/// Snapshot rates the server can keep, which start-up also holds to at most `tps`.
+pub const SNAPSHOT_RATE_RANGE: RangeInclusive<f32> = 1.0..=1000.0;
+/// Input rates the client and the load test bots accept, bounded like `TPS_RANGE` so the interval
+/// between inputs is always one a timer can keep.
+pub const INPUT_RATE_RANGE: RangeInclusive<f32> = 1.0..=1000.0;
+/// Longest any duration key can be, a day, so durations can be added up without overflowing.
+pub const MAX_SECONDS: f32 = 86_400.0;
+
+/// Environment variables are these keys in upper case with this prefix, e.g. `GAME_TPS`.
+pub const ENV_PREFIX: &str = "GAME_";
+/// Names the config file, for when there is no `--config` flag.
+pub const CONFIG_ENV: &str = "GAME_CONFIG";
+
+/// Every key `Config::set` accepts, in the order they are documented.
+pub const KEYS: [&str; 21] = [
+    "addr",
+    "metrics_addr",
+    "tps",
+    "snapshot_rate",
+    "max_players",
+    "connection_timeout",
+    "reconnect_grace",
+    "shutdown_timeout",
+    "engine_queue_capacity",
+    "connection_quota",
+    "overload_policy",
+    "acceleration",
+    "max_velocity",
+    "friction",
+    "world_width",
+    "world_height",
+    "spawn_x",
+    "spawn_y",
+    "spawn_radius",
+    "input_timeout",
+    "log_filter",
+];
+
+#[derive(Clone, Debug)]
 pub struct Config {
     pub addr: SocketAddr,
+    /// Simulation ticks per second.
+    pub tps: f32,
+    /// Snapshots per second sent to each client, clients can ask for fewer but not more.
+    pub snapshot_rate: f32,
+    /// Clients that can be joined at the same time.
+    pub max_players: usize,
+    pub connection_timeout: Duration,
+    /// How long a player stays after its connection timed out, for the client to join again with
+    /// its session token. Zero removes the player straight away.
+    pub reconnect_grace: Duration,
+    /// Longest a shutdown may take, from the signal to the process exiting.
+    pub shutdown_timeout: Duration,
+    /// Packets waiting between the server and the engine, shared by every connection.
+    pub engine_queue_capacity: usize,
+    /// Packets a single connection may have waiting when the engine falls behind.
+    pub connection_quota: usize,
+    pub overload_policy: OverloadPolicy,
+    /// Where to serve metrics over HTTP, nothing is served when this is `None`.
+    pub metrics_addr: Option<SocketAddr>,
+    pub tunables: Tunables,
+    /// Which log events to keep, in `RUST_LOG` syntax. `RUST_LOG` itself still wins.
+    pub log_filter: String,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ConfigError {
+    Read {
+        path: PathBuf,
+        reason: String,
+    },
+    Syntax {
+        path: PathBuf,
+        reason: String,
+    },
+    UnknownKey(String),
+    MissingValue(String),
+    Invalid {
+        key: String,
+        value: String,
+        reason: String,
+    },
 }
 
+impl Display for ConfigError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConfigError::Read { path, reason } => {
+                write!(f, "could not read {}: {reason}", path.display())
+            }
+            ConfigError::Syntax { path, reason } => {
+                write!(f, "{} is not valid TOML: {reason}", path.display())
+            }
+            ConfigError::UnknownKey(key) => write!(f, "unknown config key `{key}`"),
+            ConfigError::MissingValue(key) => write!(f, "no value given for `{key}`"),
+            ConfigError::Invalid { key, value, reason } => {
+                write!(f, "invalid value `{value}` for `{key}`: {reason}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
 impl Config {
     pub fn new() -> Self {
         Self::default()
     }
+
+    /// Builds the config from, in increasing order of precedence: the defaults, the TOML file
+    /// named by `--config` or `GAME_CONFIG`, `GAME_*` environment variables and `--key value`
+    /// flags in `args`.
+    pub fn load(args: impl IntoIterator<Item = String>) -> Result<Self, ConfigError> {
+        let flags = parse_args(args)?;
+        let path = file_path(&flags);
+
+        let mut config = Self::default();
+        if let Some(path) = path {
+            config.merge_file(&path)?;
+        }
+        config.merge_env(|key| std::env::var(key).ok())?;
+        for (key, value) in flags.iter().filter(|(key, _)| key != "config") {
+            config.set(key, value)?;
+        }
+        config.check()?;
+        Ok(config)
+    }
+
+    /// What `set` can not check one key at a time, since the other key may come later.
+    pub fn check(&self) -> Result<(), ConfigError> {
+        if self.snapshot_rate > self.tps {
+            return Err(ConfigError::Invalid {
+                key: "snapshot_rate".to_string(),
+                value: self.snapshot_rate.to_string(),
+                reason: format!("must not be more than tps, {}", self.tps),
+            });
+        }
+        Ok(())
+    }
+
+    /// The file `load` reads for `args`, if any.
+    pub fn path(args: impl IntoIterator<Item = String>) -> Option<PathBuf> {
+        file_path(&parse_args(args).ok()?)
+    }
+
+    pub fn merge_file(&mut self, path: &Path) -> Result<(), ConfigError> {
+        let text = std::fs::read_to_string(path).map_err(|e| ConfigError::Read {
+            path: path.to_path_buf(),
+            reason: e.to_string(),
+        })?;
+        self.merge_toml(&text).map_err(|e| match e {
+            ConfigError::Syntax { reason, .. } => ConfigError::Syntax {
+                path: path.to_path_buf(),
+                reason,
+            },
+            e => e,
+        })
+    }
+
+    /// Applies every top level key in `text`. Values go through `set` like flags do, so a file
+    /// can not hold anything a flag could not.
+    pub fn merge_toml(&mut self, text: &str) -> Result<(), ConfigError> {
+        let table = toml::Table::from_str(text).map_err(|e| ConfigError::Syntax {
+            path: PathBuf::new(),
+            reason: e.message().to_string(),
+        })?;
+        for (key, value) in table {
+            let value = match value {
+                toml::Value::String(value) => value,
+                toml::Value::Integer(value) => value.to_string(),
+                toml::Value::Float(value) => value.to_string(),
+                toml::Value::Boolean(value) => value.to_string(),
+                value => {
+                    return Err(ConfigError::Invalid {
+                        key,
+                        value: value.to_string(),
+                        reason: format!("expected a single value, found {}", value.type_str()),
+                    })
+                }
+            };
+            self.set(&key, &value)?;
+        }
+        Ok(())
+    }
+
+    /// Applies `GAME_<KEY>` for every key that `var` has a value for.
+    pub fn merge_env(&mut self, var: impl Fn(&str) -> Option<String>) -> Result<(), ConfigError> {
+        for key in KEYS {
+            if let Some(value) = var(&format!("{ENV_PREFIX}{}", key.to_uppercase())) {
+                self.set(key, &value)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Sets one key from its text form. Durations are in seconds, `metrics_addr` can be `off`.
+    pub fn set(&mut self, key: &str, value: &str) -> Result<(), ConfigError> {
+        let value = value.trim();
+        let invalid = |reason: &str| ConfigError::Invalid {
+            key: key.to_string(),
+            value: value.to_string(),
+            reason: reason.to_string(),
+        };
+
+        match key {
+            "addr" => self.addr = parse(key, value)?,
+            "metrics_addr" => {
+                self.metrics_addr = match value {
+                    "" | "off" | "none" => None,
+                    value => Some(parse(key, value)?),
+                }
+            }
+            "tps" => self.tps = parse_tps(value)?,
+            "snapshot_rate" => self.snapshot_rate = within(key, value, SNAPSHOT_RATE_RANGE)?,
+            "max_players" => {
+                // Every player goes out in each snapshot, which has to fit in one datagram.
+                let max_players = nonzero(key, value)?;
+                if max_players > Snapshot::MAX_PLAYERS {
+                    return Err(invalid(&format!(
+                        "must be at most {}, as many as fit in a snapshot",
+                        Snapshot::MAX_PLAYERS
+                    )));
+                }
+                self.max_players = max_players;
+            }
+            "connection_timeout" => self.connection_timeout = duration(key, value, positive)?,
+            "reconnect_grace" => self.reconnect_grace = duration(key, value, non_negative)?,
+            "shutdown_timeout" => self.shutdown_timeout = duration(key, value, positive)?,
+            "engine_queue_capacity" => self.engine_queue_capacity = nonzero(key, value)?,
+            "connection_quota" => self.connection_quota = nonzero(key, value)?,
+            "overload_policy" => {
+                self.overload_policy = match value {
+                    "drop_oldest" => OverloadPolicy::DropOldest,
+                    "drop_newest" => OverloadPolicy::DropNewest,
+                    _ => return Err(invalid("expected `drop_oldest` or `drop_newest`")),
+                }
+            }
+            "acceleration" => {
+                let acceleration: i8 = parse(key, value)?;
+                if acceleration <= 0 {
+                    return Err(invalid("must be greater than 0"));
+                }
+                self.tunables.acceleration = acceleration;
+            }
+            "max_velocity" => self.tunables.max_velocity = positive(key, value)?,
+            "friction" => self.tunables.friction = non_negative(key, value)?,
+            "world_width" => self.tunables.world_width = positive(key, value)?,
+            "world_height" => self.tunables.world_height = positive(key, value)?,
+            "spawn_x" => self.tunables.spawn_x = finite(key, value)?,
+            "spawn_y" => self.tunables.spawn_y = finite(key, value)?,
+            "spawn_radius" => self.tunables.spawn_radius = non_negative(key, value)?,
+            "input_timeout" => self.tunables.input_timeout = positive(key, value)?,
+            "log_filter" => {
+                EnvFilter::try_new(value).map_err(|e| invalid(&e.to_string()))?;
+                self.log_filter = value.to_string();
+            }
+            _ => return Err(ConfigError::UnknownKey(key.to_string())),
+        }
+        Ok(())
+    }
 }
 
 impl Default for Config {
     fn default() -> Self {
         Self {
             addr: "127.0.0.1:10001".parse().unwrap(),
+            tps: TPS,
+            snapshot_rate: SNAPSHOT_RATE,
+            max_players: MAX_PLAYERS,
+            connection_timeout: CONNECTION_TIMEOUT,
+            reconnect_grace: RECONNECT_GRACE,
+            shutdown_timeout: SHUTDOWN_TIMEOUT,
+            engine_queue_capacity: ENGINE_QUEUE_CAPACITY,
+            connection_quota: CONNECTION_QUOTA,
+            overload_policy: OverloadPolicy::default(),
+            metrics_addr: None,
+            tunables: Tunables::default(),
+            log_filter: DEFAULT_FILTER.to_string(),
+        }
+    }
+}
+
+/// Splits `--key value` and `--key=value` flags into pairs, with dashes in keys turned into
+/// underscores.
+fn parse_args(
+    args: impl IntoIterator<Item = String>,
+) -> Result<Vec<(String, String)>, ConfigError> {
+    let mut flags = vec![];
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        let Some(flag) = arg.strip_prefix("--") else {
+            return Err(ConfigError::UnknownKey(arg));
+        };
+        let (key, value) = match flag.split_once('=') {
+            Some((key, value)) => (key.to_string(), value.to_string()),
+            None => match args.next() {
+                Some(value) => (flag.to_string(), value),
+                None => return Err(ConfigError::MissingValue(flag.to_string())),
+            },
+        };
+        flags.push((key.replace('-', "_"), value));
+    }
+    Ok(flags)
+}
+
+fn file_path(flags: &[(String, String)]) -> Option<PathBuf> {
+    flags
+        .iter()
+        .find(|(key, _)| key == "config")
+        .map(|(_, value)| PathBuf::from(value))
+        .or_else(|| std::env::var_os(CONFIG_ENV).map(PathBuf::from))
+}
+
+fn parse<T: FromStr>(key: &str, value: &str) -> Result<T, ConfigError>
+where
+    T::Err: Display,
+{
+    value.parse().map_err(|e: T::Err| ConfigError::Invalid {
+        key: key.to_string(),
+        value: value.to_string(),
+        reason: e.to_string(),
+    })
+}
+
+/// A tick rate within `TPS_RANGE`, for the `tps` key and the admin console alike.
+pub fn parse_tps(value: &str) -> Result<f32, ConfigError> {
+    within("tps", value.trim(), TPS_RANGE)
+}
+
+fn within(key: &str, value: &str, range: RangeInclusive<f32>) -> Result<f32, ConfigError> {
+    let number: f32 = parse(key, value)?;
+    if !range.contains(&number) {
+        return Err(ConfigError::Invalid {
+            key: key.to_string(),
+            value: value.to_string(),
+            reason: format!("must be a number from {} to {}", range.start(), range.end()),
+        });
+    }
+    Ok(number)
+}
+
+/// Seconds that `number` accepts, up to `MAX_SECONDS`.
+fn duration(
+    key: &str,
+    value: &str,
+    number: fn(&str, &str) -> Result<f32, ConfigError>,
+) -> Result<Duration, ConfigError> {
+    let seconds = number(key, value)?;
+    if seconds > MAX_SECONDS {
+        return Err(ConfigError::Invalid {
+            key: key.to_string(),
+            value: value.to_string(),
+            reason: format!("must be at most {MAX_SECONDS} seconds"),
+        });
+    }
+    Ok(Duration::from_secs_f32(seconds))
+}
+
+fn positive(key: &str, value: &str) -> Result<f32, ConfigError> {
+    let number: f32 = parse(key, value)?;
+    if !number.is_finite() || number <= 0.0 {
+        return Err(ConfigError::Invalid {
+            key: key.to_string(),
+            value: value.to_string(),
+            reason: "must be a number greater than 0".to_string(),
+        });
+    }
+    Ok(number)
+}
+
+fn non_negative(key: &str, value: &str) -> Result<f32, ConfigError> {
+    let number = finite(key, value)?;
+    if number < 0.0 {
+        return Err(ConfigError::Invalid {
+            key: key.to_string(),
+            value: value.to_string(),
+            reason: "must not be negative".to_string(),
+        });
+    }
+    Ok(number)
+}
+
+fn finite(key: &str, value: &str) -> Result<f32, ConfigError> {
+    let number: f32 = parse(key, value)?;
+    if !number.is_finite() {
+        return Err(ConfigError::Invalid {
+            key: key.to_string(),
+            value: value.to_string(),
+            reason: "must be a finite number".to_string(),
+        });
+    }
+    Ok(number)
+}
+
+fn nonzero(key: &str, value: &str) -> Result<usize, ConfigError> {
+    match parse(key, value)? {
+        0 => Err(ConfigError::Invalid {
+            key: key.to_string(),
+            value: value.to_string(),
+            reason: "must be greater than 0".to_string(),
+        }),
+        number => Ok(number),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    mod parse {
+        use crate::backpressure::OverloadPolicy;
+        use crate::config::{parse_args, Config, ConfigError, KEYS};
+        use std::collections::HashMap;
+        use std::time::Duration;
+
+        #[test]
+        fn every_key_can_be_set() {
+            let values = HashMap::from([
+                ("addr", "0.0.0.0:9000"),
+                ("metrics_addr", "127.0.0.1:9100"),
+                ("overload_policy", "drop_newest"),
+                ("log_filter", "debug"),
+            ]);
+            let mut config = Config::default();
+            for key in KEYS {
+                let value = values.get(key).copied().unwrap_or("2");
+                assert_eq!(config.set(key, value), Ok(()), "{key}");
+            }
+            assert_eq!(config.overload_policy, OverloadPolicy::DropNewest);
+            assert_eq!(config.connection_timeout, Duration::from_secs(2));
+            assert_eq!(config.reconnect_grace, Duration::from_secs(2));
+            assert_eq!(config.shutdown_timeout, Duration::from_secs(2));
+            assert_eq!(config.tunables.acceleration, 2);
+        }
+
+        #[test]
+        fn toml_file() {
+            let mut config = Config::default();
+            config
+                .merge_toml(
+                    r#"
+                    addr = "0.0.0.0:10001"
+                    tps = 30
+                    snapshot_rate = 15.5
+                    max_velocity = 12.5
+                    metrics_addr = "off"
+                    "#,
+                )
+                .unwrap();
+            assert_eq!(config.addr, "0.0.0.0:10001".parse().unwrap());
+            assert_eq!(config.tps, 30.0);
+            assert_eq!(config.snapshot_rate, 15.5);
+            assert_eq!(config.tunables.max_velocity, 12.5);
+            assert_eq!(config.metrics_addr, None);
+        }
+
+        #[test]
+        fn example_file_is_valid() {
+            let mut config = Config::default();
+            config
+                .merge_toml(include_str!("../server.example.toml"))
+                .unwrap();
+            assert_eq!(config.addr, Config::default().addr);
+        }
+
+        #[test]
+        fn errors_name_the_key() {
+            let mut config = Config::default();
+            let Err(ConfigError::Invalid { key, .. }) = config.merge_toml("tps = -1") else {
+                panic!("Expected an invalid value");
+            };
+            assert_eq!(key, "tps");
+
+            let Err(ConfigError::Invalid { key, .. }) = config.merge_toml("[world]\nwidth = 5")
+            else {
+                panic!("Expected an invalid value");
+            };
+            assert_eq!(key, "world");
+
+            assert_eq!(
+                config.set("tick_rate", "20"),
+                Err(ConfigError::UnknownKey("tick_rate".to_string()))
+            );
+            assert!(config.set("overload_policy", "drop_all").is_err());
+            assert!(config.set("max_players", "0").is_err());
+            assert!(config.set("log_filter", "???==").is_err());
+            assert!(config.merge_toml("tps = ").is_err());
+        }
+
+        #[test]
+        fn values_out_of_range_are_rejected() {
+            let mut config = Config::default();
+            for (key, value) in [
+                ("tps", "1e-30"),
+                ("tps", "0.5"),
+                ("tps", "1e10"),
+                ("snapshot_rate", "1e-30"),
+                ("snapshot_rate", "NaN"),
+                ("connection_timeout", "1e30"),
+                ("reconnect_grace", "86401"),
+                ("shutdown_timeout", "1e30"),
+                ("max_players", "146"),
+                ("max_players", "100000"),
+            ] {
+                let Err(ConfigError::Invalid { key: named, .. }) = config.set(key, value) else {
+                    panic!("Expected {key} = {value} to be invalid");
+                };
+                assert_eq!(named, key);
+            }
+            assert_eq!(config.tps, Config::default().tps);
+            assert_eq!(config.set("tps", "1000"), Ok(()));
+            assert_eq!(config.set("reconnect_grace", "86400"), Ok(()));
+            assert_eq!(config.set("max_players", "145"), Ok(()));
+        }
+
+        #[test]
+        fn snapshot_rate_can_not_exceed_tps() {
+            let mut config = Config::default();
+            config.merge_toml("snapshot_rate = 30\ntps = 30").unwrap();
+            assert_eq!(config.check(), Ok(()));
+
+            config.set("tps", "10").unwrap();
+            let Err(ConfigError::Invalid { key, .. }) = config.check() else {
+                panic!("Expected an invalid value");
+            };
+            assert_eq!(key, "snapshot_rate");
+        }
+
+        #[test]
+        fn environment_uses_prefixed_upper_case_keys() {
+            let mut config = Config::default();
+            config
+                .merge_env(|var| match var {
+                    "GAME_MAX_PLAYERS" => Some("8".to_string()),
+                    "GAME_CONNECTION_TIMEOUT" => Some("2.5".to_string()),
+                    _ => None,
+                })
+                .unwrap();
+            assert_eq!(config.max_players, 8);
+            assert_eq!(config.connection_timeout, Duration::from_millis(2500));
+        }
+
+        #[test]
+        fn flags() {
+            let args = [
+                "--max-players",
+                "8",
+                "--addr=0.0.0.0:1",
+                "--config",
+                "a.toml",
+            ];
+            let flags = parse_args(args.map(String::from)).unwrap();
+            assert_eq!(
+                flags,
+                [
+                    ("max_players".to_string(), "8".to_string()),
+                    ("addr".to_string(), "0.0.0.0:1".to_string()),
+                    ("config".to_string(), "a.toml".to_string()),
+                ]
+            );
+
+            assert_eq!(
+                parse_args(["--tps".to_string()]),
+                Err(ConfigError::MissingValue("tps".to_string()))
+            );
+            assert!(parse_args(["tps".to_string()]).is_err());
         }
     }
 }
@@ -1,80 +1,381 @@
+use crate::admin::AdminCommand;
+use crate::clock::{Clock, RealClock};
+use crate::config::Config;
 use crate::entity::Entity;
-use crate::physics::Velocity;
-use crate::player::{KeyboardInput, Player};
+use crate::metrics::Metrics;
+use crate::player::KeyboardInput;
+use crate::profile::{Phase, TickProfiler};
 use net::id::Id;
+use net::nickname::Nickname;
+use net::packet::snapshot::{EntityState, Snapshot};
 use net::packet::Packet;
+use sim_core::state::{apply_inputs, tick_entities, tick_players, Input, State};
+use sim_core::tunables::Tunables;
 use std::collections::HashMap;
-use std::time::Duration;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 use tokio::sync::mpsc::Receiver;
-use tokio::time::{timeout, Instant};
+use tokio::sync::watch;
+use tracing::{debug_span, info, warn};
 
-pub const TPS: f32 = 20.0;
+pub use sim_core::TPS;
+
+/// Most ticks run back to back to catch up after a stall, anything beyond that is dropped.
+pub const MAX_CATCH_UP_TICKS: u32 = 5;
+
+/// What the server hands to the engine, in the order it happened.
+#[derive(Clone, Debug)]
+pub enum Message {
+    /// A player joined and should be spawned.
+    Join {
+        id: Id,
+        nickname: Nickname,
+    },
+    /// A player left and should be removed.
+    Leave(Id),
+    /// A player's client went quiet. The player stays, stopped, in case the client comes back.
+    Suspend(Id),
+    Packet(Packet),
+}
+
+impl From<Packet> for Message {
+    fn from(packet: Packet) -> Self {
+        Message::Packet(packet)
+    }
+}
 
 pub struct Engine {
     pub tps: f32,
-    pub players: HashMap<Id, Player>,
-    pub entities: Vec<Entity>,
+    pub state: State,
+    pub inputs: Vec<Input>,
 
-    pub previous: Instant,
-    pub lag: u128,
+    pub clock: Box<dyn Clock>,
+    pub previous: Option<Instant>,
+    pub lag: Duration,
+    pub current_tick: u64,
+    pub max_catch_up_ticks: u32,
+    /// Total simulation time skipped because the engine fell too far behind.
+    pub dropped: Duration,
 
-    pub server_rx: Option<Receiver<Packet>>,
+    pub server_rx: Option<Receiver<Message>>,
+    /// Most messages waiting in `server_rx` at once over the last tick interval, counting the ones
+    /// handled between ticks.
+    pub queue_depth: usize,
+    pub max_queue_depth: usize,
+    /// Most messages waiting in `server_rx` at once since the last tick, see `queue_depth`.
+    pub interval_queue_depth: usize,
+    pub admin_rx: Option<Receiver<AdminCommand>>,
+    /// Latest snapshot, for the server to send out on its own schedule.
+    pub snapshot_tx: Option<watch::Sender<Snapshot>>,
+    pub metrics: Arc<Metrics>,
+    pub profiler: TickProfiler,
 }
 
 impl Engine {
+    /// An engine ticking at `config.tps` with `config.tunables`, and no players until clients join.
+    /// Channels to the server and the admin console are left for the caller to hook up.
+    pub fn from_config(config: &Config) -> Self {
+        let mut engine = Self {
+            tps: config.tps,
+            ..Default::default()
+        };
+        engine.state.tunables = config.tunables;
+        engine.state.players.clear();
+        engine
+    }
+
+    /// Ticks until the server hangs up, then runs one last tick so whatever it handed over before
+    /// that, like players leaving, is applied and published.
     pub async fn run(&mut self) {
-        let ms_per_tick = (1000.0 / self.tps) as u128;
         loop {
-            let now = Instant::now();
-            let elapsed = self.previous.elapsed();
-
-            self.input().await;
-            self.lag += elapsed.as_millis();
-            if self.lag >= ms_per_tick {
-                self.tick();
-                self.lag -= ms_per_tick;
-            } else {
-                tokio::time::sleep(Duration::from_millis((ms_per_tick - self.lag) as u64)).await;
+            let next_tick = tokio::time::Instant::now() + self.until_next_tick();
+            tokio::select! {
+                biased;
+                _ = tokio::time::sleep_until(next_tick) => {
+                    self.advance();
+                }
+                message = recv(&mut self.server_rx) => match message {
+                    Some(message) => {
+                        let started = Instant::now();
+                        // Counting the message just taken out, so a full queue reads as full.
+                        let depth = self.server_rx.as_ref().map_or(0, |rx| rx.len() + 1);
+                        self.observe_queue_depth(depth);
+                        self.handle_message(message);
+                        self.profiler.record(Phase::Input, started);
+                    }
+                    None => break,
+                },
             }
+        }
+
+        self.step(1);
+        info!(tick = self.current_tick, "engine stopped");
+    }
+
+    pub fn tick_duration(&self) -> Duration {
+        Duration::from_secs_f64(1.0 / self.tps as f64)
+    }
+
+    fn until_next_tick(&self) -> Duration {
+        let since_previous = match self.previous {
+            Some(previous) => self.clock.now().saturating_duration_since(previous),
+            None => Duration::ZERO,
+        };
+        self.tick_duration()
+            .saturating_sub(self.lag)
+            .saturating_sub(since_previous)
+    }
+
+    /// Adds the time passed on the clock since the last call to the accumulator and runs as many
+    /// ticks as it covers, at most `max_catch_up_ticks`. Returns the number of ticks run.
+    pub fn advance(&mut self) -> u32 {
+        self.drain_admin();
+        self.drain_input();
+
+        let now = self.clock.now();
+        let previous = self.previous.replace(now).unwrap_or(now);
+        self.lag += now.saturating_duration_since(previous);
+
+        let tick_duration = self.tick_duration();
+        let mut ticks = 0;
+        while self.lag >= tick_duration && ticks < self.max_catch_up_ticks {
+            self.tick();
+            self.lag -= tick_duration;
+            ticks += 1;
+        }
+
+        if self.lag >= tick_duration {
+            let remainder =
+                Duration::from_nanos((self.lag.as_nanos() % tick_duration.as_nanos()) as u64);
+            let dropped = self.lag - remainder;
+            self.dropped += dropped;
+            self.lag = remainder;
+            warn!(
+                tick = self.current_tick,
+                ?dropped,
+                total_dropped = ?self.dropped,
+                "engine fell behind"
+            );
+        }
+
+        if ticks > 0 {
+            self.publish_snapshot();
+            self.finish_update(ticks);
+        }
+
+        ticks
+    }
+
+    /// Runs `n` ticks back to back without waiting, applying whatever input is already queued
+    /// before each one.
+    pub fn step(&mut self, n: usize) {
+        for _ in 0..n {
+            self.drain_admin();
+            self.drain_input();
+            self.tick();
+        }
+        self.publish_snapshot();
+        self.finish_update(n as u32);
+    }
+
+    pub fn snapshot(&self) -> Snapshot {
+        let mut players: Vec<EntityState> = self
+            .state
+            .players
+            .values()
+            .map(|player| EntityState {
+                id: player.id,
+                position: player.position,
+            })
+            .collect();
+        players.sort_by_key(|player| player.id.0);
+
+        // Every client gets the same snapshot, so entities fill whatever room is left in order.
+        let room = Snapshot::MAX_PLAYERS.saturating_sub(players.len());
+        let entities = self
+            .state
+            .entities
+            .iter()
+            .take(room)
+            .map(|entity| EntityState {
+                id: entity.id,
+                position: entity.position,
+            })
+            .collect();
+
+        Snapshot {
+            tick: self.current_tick as u32,
+            players,
+            entities,
+        }
+    }
+
+    fn publish_snapshot(&mut self) {
+        if self.snapshot_tx.is_none() {
+            return;
+        }
+
+        let started = Instant::now();
+        let snapshot = self.snapshot();
+        self.profiler.record(Phase::Snapshot, started);
+
+        let started = Instant::now();
+        if let Some(tx) = &self.snapshot_tx {
+            tx.send_replace(snapshot);
+        }
+        self.profiler.record(Phase::Send, started);
+    }
 
-            self.previous = now;
+    /// Closes the profile of an update that ran `ticks` ticks, which gets that many tick
+    /// intervals as its budget.
+    fn finish_update(&mut self, ticks: u32) {
+        let budget = self.tick_duration() * ticks.max(1);
+        let timings = self.profiler.finish(self.current_tick, budget);
+        self.metrics.observe_tick(timings.total(), budget);
+    }
+
+    /// Swaps in new gameplay parameters, logging what changed.
+    pub fn set_tunables(&mut self, tunables: Tunables) {
+        let previous = self.state.tunables;
+        if previous == tunables {
+            return;
         }
+        info!(
+            tick = self.current_tick,
+            ?previous,
+            current = ?tunables,
+            "changing tunables"
+        );
+        self.state.tunables = tunables;
+    }
+
+    /// Changes the tick rate without changing how fast things move, since every tick is simulated
+    /// with its real duration.
+    pub fn set_tps(&mut self, tps: f32) {
+        info!(from = self.tps, to = tps, "changing tick rate");
+        self.tps = tps;
     }
 
     fn tick(&mut self) {
-        for (_id, player) in self.players.iter_mut() {
-            player.tick();
-        }
-
-        for entity in self.entities.iter_mut() {
-            entity.tick()
-        }
-    }
-
-    async fn input(&mut self) {
-        if let Some(rx) = &mut self.server_rx {
-            if let Ok(Some(packet)) = timeout(Duration::from_millis(1), rx.recv()).await {
-                match packet {
-                    Packet::Ping(_) => {}
-                    Packet::Sync(_) => {}
-                    Packet::Movement(movement) => {
-                        let id = movement.id;
-                        let keyboard_input = KeyboardInput {
-                            up: movement.up,
-                            down: movement.down,
-                            left: movement.left,
-                            right: movement.right,
-                        };
-                        if let Some(player) = self.players.get_mut(&id) {
-                            player.input(keyboard_input)
-                        }
-                    }
+        let _span = debug_span!("tick", tick = self.current_tick).entered();
+        let dt = self.tick_duration().as_secs_f32();
+
+        let started = Instant::now();
+        apply_inputs(&mut self.state, &self.inputs);
+        self.inputs.clear();
+        self.profiler.record(Phase::Input, started);
+
+        let started = Instant::now();
+        tick_players(&mut self.state, dt);
+        self.profiler.record(Phase::Players, started);
+
+        let started = Instant::now();
+        tick_entities(&mut self.state, dt);
+        self.profiler.record(Phase::Entities, started);
+
+        self.current_tick += 1;
+        self.metrics
+            .players
+            .store(self.state.players.len() as u64, Ordering::Relaxed);
+    }
+
+    fn drain_admin(&mut self) {
+        while let Some(command) = self.admin_rx.as_mut().and_then(|rx| rx.try_recv().ok()) {
+            match command {
+                AdminCommand::SetTps(tps) => self.set_tps(tps),
+                AdminCommand::SetTunables(tunables) => self.set_tunables(tunables),
+                // Handled by the console before they reach the engine.
+                AdminCommand::SetLogFilter(_) | AdminCommand::Reload => {}
+            }
+        }
+    }
+
+    /// Handles every message already waiting in `server_rx` without waiting for more.
+    fn drain_input(&mut self) {
+        let Some(rx) = &self.server_rx else {
+            return;
+        };
+        let started = Instant::now();
+
+        let capacity = rx.max_capacity();
+        self.observe_queue_depth(rx.len());
+        self.queue_depth = std::mem::take(&mut self.interval_queue_depth);
+        self.metrics
+            .engine_queue_depth
+            .store(self.queue_depth as u64, Ordering::Relaxed);
+        if self.queue_depth > 0 && self.queue_depth == capacity {
+            warn!(
+                depth = self.queue_depth,
+                tick = self.current_tick,
+                "input queue full"
+            );
+        }
+
+        while let Some(message) = self.server_rx.as_mut().and_then(|rx| rx.try_recv().ok()) {
+            self.handle_message(message);
+        }
+        self.profiler.record(Phase::Input, started);
+    }
+
+    fn observe_queue_depth(&mut self, depth: usize) {
+        self.interval_queue_depth = self.interval_queue_depth.max(depth);
+        self.max_queue_depth = self.max_queue_depth.max(depth);
+    }
+
+    fn handle_message(&mut self, message: Message) {
+        match message {
+            Message::Join { id, nickname } => {
+                info!(id = id.0, %nickname, "spawning player");
+                self.state.spawn(id);
+            }
+            Message::Leave(id) => {
+                if self.state.players.remove(&id).is_some() {
+                    info!(id = id.0, "removed player");
+                }
+            }
+            Message::Suspend(id) => {
+                if let Some(player) = self.state.players.get_mut(&id) {
+                    player.stop();
+                    info!(id = id.0, "suspended player");
                 }
             }
+            Message::Packet(packet) => self.handle_packet(packet),
+        }
+    }
+
+    fn handle_packet(&mut self, packet: Packet) {
+        match packet {
+            Packet::Ping(_) => {}
+            Packet::Sync(_) => {}
+            Packet::Join(_) => {}
+            Packet::Welcome(_) => {}
+            Packet::Snapshot(_) => {}
+            Packet::Disconnect(_) => {}
+            Packet::Movement(movement) => {
+                let id = movement.id;
+                let keyboard_input = KeyboardInput {
+                    up: movement.up,
+                    down: movement.down,
+                    left: movement.left,
+                    right: movement.right,
+                };
+                self.inputs.push(Input {
+                    id,
+                    keyboard: keyboard_input,
+                });
+            }
         }
     }
 }
 
+async fn recv<T>(rx: &mut Option<Receiver<T>>) -> Option<T> {
+    match rx {
+        Some(rx) => rx.recv().await,
+        None => std::future::pending().await,
+    }
+}
+
 fn create_n_entities(n: u16) -> Vec<Entity> {
     let mut output = vec![];
     for i in 0..n {
@@ -87,39 +388,71 @@ impl Default for Engine {
     fn default() -> Self {
         Self {
             tps: TPS,
-            players: {
-                let player = Player {
-                    velocity: Velocity {
-                        x: 0.0,
-                        y: 0.0,
-                        max_x: 10.0,
-                        max_y: 10.0,
-                    },
-                    ..Default::default()
+            state: {
+                let mut state = State {
+                    players: HashMap::new(),
+                    entities: create_n_entities(u16::MAX),
+                    tunables: Tunables::default(),
                 };
-                HashMap::from([(player.id, player)])
+                state.spawn(Id::default());
+                state
             },
-            entities: create_n_entities(u16::MAX),
-            previous: Instant::now(),
-            lag: 0,
+            inputs: vec![],
+            clock: Box::new(RealClock),
+            previous: None,
+            lag: Duration::ZERO,
+            current_tick: 0,
+            max_catch_up_ticks: MAX_CATCH_UP_TICKS,
+            dropped: Duration::ZERO,
             server_rx: None,
+            queue_depth: 0,
+            max_queue_depth: 0,
+            interval_queue_depth: 0,
+            admin_rx: None,
+            snapshot_tx: None,
+            metrics: Arc::new(Metrics::default()),
+            profiler: TickProfiler::new(),
         }
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::engine::{Engine, TPS};
+    use crate::admin::AdminCommand;
+    use crate::clock::ManualClock;
+    use crate::config::Config;
+    use crate::engine::{Engine, Message};
+    use crate::metrics::Metrics;
     use crate::physics::{Acceleration, Velocity};
+    use net::frame::{Frame, MAX_DATAGRAM};
     use net::id::Id;
-    use std::thread;
+    use net::packet::movement::Movement;
+    use net::packet::snapshot::Snapshot;
+    use net::packet::Packet;
+    use sim_core::state::State;
+    use sim_core::tunables::Tunables;
+    use std::sync::atomic::Ordering;
+    use std::sync::Arc;
     use std::time::Duration;
+    use tokio::sync::mpsc::channel;
+    use tokio::time::timeout;
+
+    fn engine_with_clock(clock: &ManualClock) -> Engine {
+        let mut engine = Engine {
+            state: State::default(),
+            clock: Box::new(clock.clone()),
+            ..Default::default()
+        };
+        engine.advance();
+        engine
+    }
 
     #[test]
-    fn test() {
+    fn acceleration_builds_up_velocity_every_step() {
+        let close = |a: f32, b: f32| (a - b).abs() < 1e-5;
         let mut engine = Engine::default();
         let id = Id(0);
-        let player = engine.players.get_mut(&id).unwrap();
+        let player = engine.state.players.get_mut(&id).unwrap();
         player.velocity = Velocity {
             x: 0.0,
             y: 0.0,
@@ -128,32 +461,341 @@ mod tests {
         };
         player.acceleration = Acceleration { x: 1, y: 0 };
 
-        let tick = |engine: &mut Engine| {
-            engine.tick();
-            let player = engine.players.get_mut(&id).unwrap();
-            println!(
-            "||   {:7.prec$} | {:7.prec$}   ||   {:7.prec$} | {:7.prec$}   ||   {:2} | {:2}   ||",
-            player.position.x,
-            player.position.y,
-            player.velocity.x,
-            player.velocity.y,
-            player.acceleration.x,
-            player.acceleration.y,
-            prec = 4,
-        );
+        // 0.05s a tick at 20 ticks per second, so 0.05 more velocity each tick.
+        engine.step(5);
+        assert_eq!(engine.current_tick, 5);
+        let player = engine.state.players.get_mut(&id).unwrap();
+        assert!(close(player.velocity.x, 0.25), "{:?}", player.velocity);
+        assert_eq!(player.velocity.y, 0.0);
+        assert!(close(player.position.x, 0.0375), "{:?}", player.position);
+        assert_eq!(player.position.y, 0.0);
+
+        player.acceleration = Acceleration { x: -1, y: -1 };
+        // As new input would, or the keys count as released before the last tick.
+        player.since_input = 0.0;
+        engine.step(5);
+        assert_eq!(engine.current_tick, 10);
+        let player = &engine.state.players[&id];
+        assert!(close(player.velocity.x, 0.0), "{:?}", player.velocity);
+        assert!(close(player.velocity.y, -0.25), "{:?}", player.velocity);
+        assert!(close(player.position.x, 0.0625), "{:?}", player.position);
+        assert!(close(player.position.y, -0.0375), "{:?}", player.position);
+    }
+
+    #[test]
+    fn step_is_deterministic() {
+        let run = || {
+            let (tx, rx) = channel(5);
+            let mut engine = Engine {
+                server_rx: Some(rx),
+                ..Default::default()
+            };
+            let movement = Movement {
+                id: Id(0),
+                right: true,
+                up: true,
+                ..Default::default()
+            };
+            tx.try_send(Packet::Movement(movement).into()).unwrap();
+            engine.step(40);
+            engine.state.players[&Id(0)].position
+        };
+
+        let position = run();
+        assert!(position.x > 0.0 && position.y > 0.0);
+        assert_eq!(position, run());
+    }
+
+    #[test]
+    fn step_drains_queued_input_before_ticking() {
+        let (tx, rx) = channel(5);
+        let mut engine = Engine {
+            server_rx: Some(rx),
+            ..Default::default()
+        };
+        let movement = Movement {
+            id: Id(0),
+            left: true,
+            ..Default::default()
+        };
+        tx.try_send(Packet::Movement(movement).into()).unwrap();
+
+        engine.step(1);
+        let player = &engine.state.players[&Id(0)];
+        assert_eq!(player.acceleration.x, -10);
+        assert!(player.velocity.x < 0.0);
+    }
+
+    #[test]
+    fn keys_are_released_when_input_stops() {
+        let (tx, rx) = channel(5);
+        let mut engine = Engine {
+            server_rx: Some(rx),
+            ..Default::default()
+        };
+        let movement = Movement {
+            id: Id(0),
+            up: true,
+            right: true,
+            ..Default::default()
+        };
+        tx.try_send(Packet::Movement(movement).into()).unwrap();
+
+        // Just under the 0.5s input timeout at 20 ticks per second.
+        engine.step(9);
+        assert_eq!(engine.state.players[&Id(0)].acceleration.y, 10);
+
+        engine.step(2);
+        let player = &engine.state.players[&Id(0)];
+        assert_eq!(player.acceleration.x, 0);
+        assert_eq!(player.acceleration.y, 0);
+    }
+
+    #[test]
+    fn suspended_players_stop_but_stay() {
+        let (tx, rx) = channel(5);
+        let mut engine = Engine {
+            server_rx: Some(rx),
+            ..Default::default()
+        };
+        let movement = Movement {
+            id: Id(0),
+            right: true,
+            ..Default::default()
         };
+        tx.try_send(Packet::Movement(movement).into()).unwrap();
+        engine.step(2);
+        assert!(engine.state.players[&Id(0)].velocity.x > 0.0);
 
-        let steps = 20;
-        for _ in 0..steps {
-            tick(&mut engine);
-            thread::sleep(Duration::from_millis((1000.0 / TPS) as u64))
+        tx.try_send(Message::Suspend(Id(0))).unwrap();
+        engine.step(1);
+        let position = engine.state.players[&Id(0)].position;
+        engine.step(5);
+        let player = &engine.state.players[&Id(0)];
+        assert_eq!(player.velocity.x, 0.0);
+        assert_eq!(player.position, position);
+    }
+
+    #[test]
+    fn advance_runs_one_tick_per_tick_duration() {
+        let clock = ManualClock::new();
+        let mut engine = engine_with_clock(&clock);
+
+        clock.advance(Duration::from_millis(49));
+        assert_eq!(engine.advance(), 0);
+        clock.advance(Duration::from_millis(1));
+        assert_eq!(engine.advance(), 1);
+        clock.advance(Duration::from_millis(120));
+        assert_eq!(engine.advance(), 2);
+
+        assert_eq!(engine.current_tick, 3);
+        assert_eq!(engine.lag, Duration::from_millis(20));
+    }
+
+    #[test]
+    fn advance_does_not_drift_with_sub_millisecond_ticks() {
+        let clock = ManualClock::new();
+        let mut engine = engine_with_clock(&clock);
+        engine.tps = 30.0;
+
+        for _ in 0..10_000 {
+            clock.advance(Duration::from_millis(1));
+            engine.advance();
         }
 
-        let player = engine.players.get_mut(&id).unwrap();
-        player.acceleration = Acceleration { x: -1, y: -1 };
-        for _ in 0..steps {
-            tick(&mut engine);
-            thread::sleep(Duration::from_millis((1000.0 / TPS) as u64))
+        assert_eq!(engine.current_tick, 300);
+        assert_eq!(engine.dropped, Duration::ZERO);
+    }
+
+    #[test]
+    fn advance_caps_catch_up_and_reports_dropped_time() {
+        let clock = ManualClock::new();
+        let mut engine = engine_with_clock(&clock);
+        engine.max_catch_up_ticks = 5;
+
+        clock.advance(Duration::from_millis(10_010));
+        assert_eq!(engine.advance(), 5);
+        assert_eq!(engine.dropped, Duration::from_millis(9_750));
+        assert_eq!(engine.lag, Duration::from_millis(10));
+
+        clock.advance(Duration::from_millis(40));
+        assert_eq!(engine.advance(), 1);
+        assert_eq!(engine.current_tick, 6);
+    }
+
+    #[test]
+    fn tick_rate_does_not_change_gameplay_speed() {
+        let position_after_one_second = |tps: f32| {
+            let mut engine = Engine {
+                tps,
+                ..Default::default()
+            };
+            let player = engine.state.players.get_mut(&Id(0)).unwrap();
+            player.velocity.x = 4.0;
+            engine.step(tps as usize);
+            engine.state.players[&Id(0)].position.x
+        };
+
+        for tps in [20.0, 30.0, 60.0] {
+            assert!((position_after_one_second(tps) - 4.0).abs() < 1e-4);
+        }
+    }
+
+    #[test]
+    fn admin_command_changes_tick_rate() {
+        let (tx, rx) = channel(1);
+        let mut engine = Engine {
+            admin_rx: Some(rx),
+            ..Default::default()
+        };
+        tx.try_send(AdminCommand::SetTps(60.0)).unwrap();
+
+        engine.step(1);
+        assert_eq!(engine.tps, 60.0);
+        assert_eq!(engine.tick_duration(), Duration::from_secs_f64(1.0 / 60.0));
+    }
+
+    #[test]
+    fn admin_command_changes_tunables_at_the_next_tick() {
+        let (tx, rx) = channel(4);
+        let mut engine = Engine {
+            admin_rx: Some(rx),
+            ..Default::default()
+        };
+        let id = Id::default();
+        engine.state.players.get_mut(&id).unwrap().velocity.x = 10.0;
+
+        let tunables = Tunables {
+            max_velocity: 5.0,
+            ..Default::default()
+        };
+        tx.try_send(AdminCommand::SetTunables(tunables)).unwrap();
+        assert_eq!(engine.state.tunables, Tunables::default());
+
+        engine.step(1);
+        assert_eq!(engine.state.tunables, tunables);
+        assert_eq!(engine.state.players[&id].velocity.max_x, 5.0);
+    }
+
+    #[test]
+    fn step_publishes_latest_snapshot() {
+        let (tx, rx) = tokio::sync::watch::channel(Default::default());
+        let mut engine = Engine {
+            snapshot_tx: Some(tx),
+            ..Default::default()
+        };
+        engine.state.players.get_mut(&Id(0)).unwrap().velocity.x = 10.0;
+
+        engine.step(3);
+        let snapshot = rx.borrow();
+        assert_eq!(snapshot.tick, 3);
+        assert_eq!(snapshot.players.len(), 1);
+        assert_eq!(snapshot.players[0].id, Id(0));
+        assert!(snapshot.players[0].position.x > 0.0);
+    }
+
+    #[test]
+    fn from_config_starts_without_players() {
+        let config = Config {
+            tps: 30.0,
+            tunables: Tunables {
+                acceleration: 20,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let engine = Engine::from_config(&config);
+        assert_eq!(engine.tps, 30.0);
+        assert_eq!(engine.state.tunables.acceleration, 20);
+        assert!(engine.state.players.is_empty());
+        assert!(!engine.state.entities.is_empty());
+    }
+
+    #[test]
+    fn snapshot_fills_up_with_entities() {
+        let engine = Engine::default();
+        let snapshot = engine.snapshot();
+        assert_eq!(snapshot.players.len(), 1);
+        assert_eq!(snapshot.entities.len(), Snapshot::MAX_PLAYERS - 1);
+        assert_eq!(snapshot.entities[0].id, Id(0));
+        assert!(Packet::Snapshot(snapshot).encoded_len() + Frame::HEADER_SIZE <= MAX_DATAGRAM);
+    }
+
+    #[test]
+    fn advance_drains_every_queued_packet() {
+        let clock = ManualClock::new();
+        let (tx, rx) = channel(100);
+        let mut engine = Engine {
+            server_rx: Some(rx),
+            ..engine_with_clock(&clock)
+        };
+        for i in 0..100 {
+            let movement = Movement {
+                id: Id(i),
+                ..Default::default()
+            };
+            tx.try_send(Packet::Movement(movement).into()).unwrap();
         }
+
+        clock.advance(Duration::from_millis(50));
+        assert_eq!(engine.advance(), 1);
+        assert_eq!(engine.queue_depth, 100);
+        assert_eq!(engine.max_queue_depth, 100);
+        assert_eq!(tx.capacity(), 100);
+    }
+
+    #[tokio::test]
+    async fn run_reports_queue_depth_seen_between_ticks() {
+        let (tx, rx) = channel(5);
+        let metrics = Arc::new(Metrics::default());
+        let mut engine = Engine {
+            tps: 10.0,
+            state: State::default(),
+            server_rx: Some(rx),
+            metrics: metrics.clone(),
+            ..Default::default()
+        };
+        for i in 0..5 {
+            let movement = Movement {
+                id: Id(i),
+                ..Default::default()
+            };
+            tx.try_send(Packet::Movement(movement).into()).unwrap();
+        }
+        let handle = tokio::spawn(async move {
+            engine.run().await;
+        });
+
+        // Past the first tick, which publishes what was seen before it, and short of the second.
+        tokio::time::sleep(Duration::from_millis(150)).await;
+        handle.abort();
+        assert_eq!(metrics.engine_queue_depth.load(Ordering::Relaxed), 5);
+    }
+
+    #[tokio::test]
+    async fn run_receives_input_between_ticks() {
+        let (tx, rx) = channel(5);
+        let mut engine = Engine {
+            state: State::default(),
+            server_rx: Some(rx),
+            ..Default::default()
+        };
+        let handle = tokio::spawn(async move {
+            engine.run().await;
+        });
+
+        // Ten times what the queue holds, in less time than a single tick takes.
+        let send = async {
+            for i in 0..50 {
+                let movement = Movement {
+                    id: Id(i),
+                    ..Default::default()
+                };
+                tx.send(Packet::Movement(movement).into()).await.unwrap();
+            }
+        };
+        let sent = timeout(Duration::from_millis(40), send).await;
+        handle.abort();
+        assert!(sent.is_ok());
     }
 }
@@ -1,5 +1,14 @@
+pub mod admin;
+pub mod backpressure;
+pub mod clock;
 pub mod config;
 pub mod engine;
-pub mod entity;
-pub mod physics;
-pub mod player;
+pub mod logging;
+pub mod metrics;
+pub mod netsim;
+pub mod profile;
+pub mod reload;
+pub mod rng;
+pub mod server;
+
+pub use sim_core::{entity, physics, player};
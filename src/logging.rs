@@ -0,0 +1,39 @@
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{fmt, reload, EnvFilter, Registry};
+
+/// Used when neither `RUST_LOG` nor the caller ask for anything else.
+pub const DEFAULT_FILTER: &str = "info";
+
+/// Changes what gets logged while the process is running.
+#[derive(Clone)]
+pub struct LogHandle(reload::Handle<EnvFilter, Registry>);
+
+impl LogHandle {
+    pub fn set_filter(&self, filter: &str) -> Result<(), String> {
+        let filter = EnvFilter::try_new(filter).map_err(|e| e.to_string())?;
+        self.0.reload(filter).map_err(|e| e.to_string())
+    }
+}
+
+/// Installs the global subscriber. `RUST_LOG` takes precedence over `filter`, using the same
+/// `target=level` directives.
+pub fn init(filter: &str) -> LogHandle {
+    init_with_writer(filter, std::io::stdout)
+}
+
+/// Like `init`, for binaries that need stdout for something else.
+pub fn init_with_writer<W>(filter: &str, writer: W) -> LogHandle
+where
+    W: for<'a> fmt::MakeWriter<'a> + Send + Sync + 'static,
+{
+    let filter = EnvFilter::try_from_default_env()
+        .or_else(|_| EnvFilter::try_new(filter))
+        .unwrap_or_else(|_| EnvFilter::new(DEFAULT_FILTER));
+    let (filter, handle) = reload::Layer::new(filter);
+    tracing_subscriber::registry()
+        .with(filter)
+        .with(fmt::layer().with_writer(writer))
+        .init();
+    LogHandle(handle)
+}
@@ -0,0 +1,338 @@
+use crate::backpressure::OverloadStats;
+use net::frame::Frame;
+use std::fmt::Write;
+use std::io;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tracing::debug;
+
+/// Upper bounds of the tick duration buckets, in seconds.
+const TICK_BUCKETS: [f64; 10] = [
+    0.0001, 0.00025, 0.0005, 0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1,
+];
+
+/// Label values for the per packet type counters, indexed by packet id. Ids the protocol does not
+/// know and datagrams too short to be a frame are counted as `unknown`, frames without a packet as
+/// `empty`.
+const PACKET_TYPES: [&str; 9] = [
+    "ping",
+    "sync",
+    "movement",
+    "snapshot",
+    "join",
+    "welcome",
+    "disconnect",
+    "unknown",
+    "empty",
+];
+const UNKNOWN: usize = 7;
+const EMPTY: usize = 8;
+
+#[derive(Debug, Default)]
+pub struct Histogram {
+    buckets: [AtomicU64; TICK_BUCKETS.len()],
+    count: AtomicU64,
+    sum_nanos: AtomicU64,
+}
+
+impl Histogram {
+    pub fn observe(&self, duration: Duration) {
+        let seconds = duration.as_secs_f64();
+        if let Some(bucket) = TICK_BUCKETS.iter().position(|&le| seconds <= le) {
+            self.buckets[bucket].fetch_add(1, Ordering::Relaxed);
+        }
+        self.count.fetch_add(1, Ordering::Relaxed);
+        self.sum_nanos
+            .fetch_add(duration.as_nanos() as u64, Ordering::Relaxed);
+    }
+
+    pub fn count(&self) -> u64 {
+        self.count.load(Ordering::Relaxed)
+    }
+
+    fn render(&self, out: &mut String, name: &str, help: &str) {
+        let _ = writeln!(out, "# HELP {name} {help}");
+        let _ = writeln!(out, "# TYPE {name} histogram");
+        let mut cumulative = 0;
+        for (le, bucket) in TICK_BUCKETS.iter().zip(&self.buckets) {
+            cumulative += bucket.load(Ordering::Relaxed);
+            let _ = writeln!(out, "{name}_bucket{{le=\"{le}\"}} {cumulative}");
+        }
+        let count = self.count();
+        let sum = self.sum_nanos.load(Ordering::Relaxed) as f64 / 1e9;
+        let _ = writeln!(out, "{name}_bucket{{le=\"+Inf\"}} {count}");
+        let _ = writeln!(out, "{name}_sum {sum}");
+        let _ = writeln!(out, "{name}_count {count}");
+    }
+}
+
+/// Counters shared by the engine and the server, rendered in the Prometheus text format.
+#[derive(Debug, Default)]
+pub struct Metrics {
+    pub tick_duration: Histogram,
+    /// Engine updates that took longer than the ticks they ran.
+    pub tick_overruns: AtomicU64,
+    pub players: AtomicU64,
+    pub connections: AtomicU64,
+    pub packets_in: [AtomicU64; PACKET_TYPES.len()],
+    pub bytes_in: [AtomicU64; PACKET_TYPES.len()],
+    pub packets_out: [AtomicU64; PACKET_TYPES.len()],
+    pub bytes_out: [AtomicU64; PACKET_TYPES.len()],
+    /// Datagrams that are not a frame, or whose packet could not be decoded.
+    pub decode_errors: AtomicU64,
+    pub engine_queue_depth: AtomicU64,
+    pub overload: Arc<OverloadStats>,
+}
+
+impl Metrics {
+    pub fn observe_tick(&self, elapsed: Duration, budget: Duration) {
+        self.tick_duration.observe(elapsed);
+        if elapsed > budget {
+            self.tick_overruns.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Counts a datagram that arrived, by the type of the packet in it.
+    pub fn received(&self, datagram: &[u8]) {
+        let packet_type = packet_type(datagram);
+        self.packets_in[packet_type].fetch_add(1, Ordering::Relaxed);
+        self.bytes_in[packet_type].fetch_add(datagram.len() as u64, Ordering::Relaxed);
+    }
+
+    /// Counts a datagram that was sent, by the type of the packet in it.
+    pub fn sent(&self, datagram: &[u8]) {
+        let packet_type = packet_type(datagram);
+        self.packets_out[packet_type].fetch_add(1, Ordering::Relaxed);
+        self.bytes_out[packet_type].fetch_add(datagram.len() as u64, Ordering::Relaxed);
+    }
+
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        self.tick_duration.render(
+            &mut out,
+            "game_tick_duration_seconds",
+            "Time spent on one engine update, from draining input to publishing the snapshot.",
+        );
+        counter(
+            &mut out,
+            "game_tick_overruns_total",
+            "Engine updates that took longer than the ticks they ran.",
+            &self.tick_overruns,
+        );
+        gauge(
+            &mut out,
+            "game_players",
+            "Players in the simulation.",
+            &self.players,
+        );
+        gauge(
+            &mut out,
+            "game_connections",
+            "Peers the server has heard from.",
+            &self.connections,
+        );
+        by_packet_type(
+            &mut out,
+            "game_packets_received_total",
+            "Datagrams received.",
+            &self.packets_in,
+        );
+        by_packet_type(
+            &mut out,
+            "game_bytes_received_total",
+            "Bytes received.",
+            &self.bytes_in,
+        );
+        by_packet_type(
+            &mut out,
+            "game_packets_sent_total",
+            "Datagrams sent.",
+            &self.packets_out,
+        );
+        by_packet_type(
+            &mut out,
+            "game_bytes_sent_total",
+            "Bytes sent.",
+            &self.bytes_out,
+        );
+        counter(
+            &mut out,
+            "game_decode_errors_total",
+            "Datagrams that could not be decoded.",
+            &self.decode_errors,
+        );
+        gauge(
+            &mut out,
+            "game_engine_queue_depth",
+            "Packets waiting for the engine when it last drained its queue.",
+            &self.engine_queue_depth,
+        );
+
+        let _ = writeln!(
+            out,
+            "# HELP game_overload_dropped_total Packets dropped because a connection's outbox was full."
+        );
+        let _ = writeln!(out, "# TYPE game_overload_dropped_total counter");
+        for (policy, value) in [
+            ("drop_oldest", &self.overload.dropped_oldest),
+            ("drop_newest", &self.overload.dropped_newest),
+        ] {
+            let value = value.load(Ordering::Relaxed);
+            let _ = writeln!(
+                out,
+                "game_overload_dropped_total{{policy=\"{policy}\"}} {value}"
+            );
+        }
+        counter(
+            &mut out,
+            "game_overload_coalesced_total",
+            "Movement inputs replaced by a newer one before the engine saw them.",
+            &self.overload.coalesced,
+        );
+        out
+    }
+}
+
+fn packet_type(datagram: &[u8]) -> usize {
+    match datagram.get(Frame::HEADER_SIZE) {
+        Some(&id) => (id as usize).min(UNKNOWN),
+        None if datagram.len() == Frame::HEADER_SIZE => EMPTY,
+        None => UNKNOWN,
+    }
+}
+
+fn counter(out: &mut String, name: &str, help: &str, value: &AtomicU64) {
+    metric(out, name, help, "counter", value)
+}
+
+fn gauge(out: &mut String, name: &str, help: &str, value: &AtomicU64) {
+    metric(out, name, help, "gauge", value)
+}
+
+fn metric(out: &mut String, name: &str, help: &str, kind: &str, value: &AtomicU64) {
+    let _ = writeln!(out, "# HELP {name} {help}");
+    let _ = writeln!(out, "# TYPE {name} {kind}");
+    let _ = writeln!(out, "{name} {}", value.load(Ordering::Relaxed));
+}
+
+fn by_packet_type(out: &mut String, name: &str, help: &str, values: &[AtomicU64]) {
+    let _ = writeln!(out, "# HELP {name} {help}");
+    let _ = writeln!(out, "# TYPE {name} counter");
+    for (packet_type, value) in PACKET_TYPES.iter().zip(values) {
+        let value = value.load(Ordering::Relaxed);
+        let _ = writeln!(out, "{name}{{type=\"{packet_type}\"}} {value}");
+    }
+}
+
+/// Answers every HTTP request on `listener` with the current metrics. Only meant to be scraped
+/// from the local network, so it reads just enough of the request to find the path.
+pub async fn serve(listener: TcpListener, metrics: Arc<Metrics>) -> io::Result<()> {
+    loop {
+        let (stream, peer) = listener.accept().await?;
+        let metrics = metrics.clone();
+        tokio::spawn(async move {
+            if let Err(e) = respond(stream, &metrics).await {
+                debug!(%peer, error = %e, "metrics request failed");
+            }
+        });
+    }
+}
+
+async fn respond(mut stream: TcpStream, metrics: &Metrics) -> io::Result<()> {
+    let mut buf = [0; 1024];
+    let n = stream.read(&mut buf).await?;
+    let request = &buf[..n];
+
+    let (status, body) = if request.starts_with(b"GET /metrics ") {
+        ("200 OK", metrics.render())
+    } else {
+        ("404 Not Found", String::new())
+    };
+    let head = format!(
+        "HTTP/1.1 {status}\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        body.len()
+    );
+    stream.write_all(head.as_bytes()).await?;
+    stream.write_all(body.as_bytes()).await?;
+    stream.shutdown().await
+}
+
+#[cfg(test)]
+mod tests {
+    mod render {
+        use crate::metrics::Metrics;
+        use std::sync::atomic::Ordering;
+        use std::time::Duration;
+
+        #[test]
+        fn tick_histogram_is_cumulative() {
+            let metrics = Metrics::default();
+            let budget = Duration::from_millis(50);
+            metrics.observe_tick(Duration::from_micros(200), budget);
+            metrics.observe_tick(Duration::from_millis(3), budget);
+            metrics.observe_tick(Duration::from_millis(70), budget);
+
+            let out = metrics.render();
+            assert!(out.contains("game_tick_duration_seconds_bucket{le=\"0.00025\"} 1\n"));
+            assert!(out.contains("game_tick_duration_seconds_bucket{le=\"0.005\"} 2\n"));
+            assert!(out.contains("game_tick_duration_seconds_bucket{le=\"0.1\"} 3\n"));
+            assert!(out.contains("game_tick_duration_seconds_bucket{le=\"+Inf\"} 3\n"));
+            assert!(out.contains("game_tick_duration_seconds_count 3\n"));
+            assert!(out.contains("game_tick_overruns_total 1\n"));
+        }
+
+        #[test]
+        fn counts_datagrams_by_packet_type() {
+            let metrics = Metrics::default();
+            metrics.received(&[1, 0, 0, 0, 0, 0, 0, 0, 0, 2, 0, 0, 0]);
+            metrics.received(&[1, 0, 0, 0, 0, 0, 0, 0, 0]);
+            metrics.received(&[1, 0, 0, 0, 0, 0, 0, 0, 0, 42]);
+            metrics.sent(&[1, 0, 0, 0, 0, 0, 0, 0, 0, 3, 0, 0, 0, 0, 0, 0]);
+            metrics.decode_errors.fetch_add(1, Ordering::Relaxed);
+
+            let out = metrics.render();
+            assert!(out.contains("game_packets_received_total{type=\"movement\"} 1\n"));
+            assert!(out.contains("game_bytes_received_total{type=\"movement\"} 13\n"));
+            assert!(out.contains("game_packets_received_total{type=\"empty\"} 1\n"));
+            assert!(out.contains("game_packets_received_total{type=\"unknown\"} 1\n"));
+            assert!(out.contains("game_bytes_sent_total{type=\"snapshot\"} 16\n"));
+            assert!(out.contains("game_decode_errors_total 1\n"));
+        }
+    }
+
+    mod http {
+        use crate::metrics::{serve, Metrics};
+        use std::sync::Arc;
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+        use tokio::net::{TcpListener, TcpStream};
+
+        async fn get(path: &str) -> String {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let addr = listener.local_addr().unwrap();
+            tokio::spawn(serve(listener, Arc::new(Metrics::default())));
+
+            let mut stream = TcpStream::connect(addr).await.unwrap();
+            let request = format!("GET {path} HTTP/1.1\r\nHost: localhost\r\n\r\n");
+            stream.write_all(request.as_bytes()).await.unwrap();
+            let mut response = String::new();
+            stream.read_to_string(&mut response).await.unwrap();
+            response
+        }
+
+        #[tokio::test]
+        async fn serves_metrics() {
+            let response = get("/metrics").await;
+            assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+            assert!(response.contains("# TYPE game_tick_duration_seconds histogram\n"));
+        }
+
+        #[tokio::test]
+        async fn unknown_path_is_not_found() {
+            let response = get("/").await;
+            assert!(response.starts_with("HTTP/1.1 404 Not Found\r\n"));
+        }
+    }
+}
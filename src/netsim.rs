@@ -0,0 +1,565 @@
+use crate::rng::Rng;
+use net::frame::MAX_DATAGRAM;
+use std::cmp::Reverse;
+use std::collections::hash_map::Entry;
+use std::collections::{BinaryHeap, HashMap};
+use std::io;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::UdpSocket;
+use tokio::sync::mpsc::{unbounded_channel, UnboundedSender};
+use tokio::task::JoinHandle;
+use tokio::time::{sleep_until, Instant};
+use tracing::{debug, info};
+
+/// How far a link may fall behind its bandwidth before it drops datagrams, like a router whose
+/// queue is full.
+pub const MAX_QUEUE_DELAY: Duration = Duration::from_secs(1);
+pub const REORDER_DELAY: Duration = Duration::from_millis(100);
+/// Every name `Conditions::set` accepts.
+pub const KEYS: [&str; 7] = [
+    "delay",
+    "jitter",
+    "loss",
+    "duplicate",
+    "reorder",
+    "reorder_delay",
+    "bandwidth",
+];
+
+/// What one direction of a simulated network does to the datagrams going through it.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Conditions {
+    /// Added to every datagram.
+    pub delay: Duration,
+    /// Up to this much more delay, picked for each datagram, so datagrams can overtake each
+    /// other.
+    pub jitter: Duration,
+    /// Chance of a datagram being dropped, from 0 to 1.
+    pub loss: f64,
+    /// Chance of a datagram arriving twice.
+    pub duplicate: f64,
+    /// Chance of a datagram being held back by `reorder_delay`, so later ones arrive first.
+    pub reorder: f64,
+    pub reorder_delay: Duration,
+    /// Bytes per second the link carries, unlimited when `None`.
+    pub bandwidth: Option<u64>,
+}
+
+impl Conditions {
+    /// Sets `key` from the command line form of its value: milliseconds for delays, 0 to 1 for
+    /// chances and kbit/s for `bandwidth`, where 0 is unlimited.
+    pub fn set(&mut self, key: &str, value: &str) -> Result<(), String> {
+        let invalid = |reason: &str| format!("invalid {key} `{value}`: {reason}");
+        let millis = || {
+            value
+                .parse::<f64>()
+                .ok()
+                .filter(|millis| millis.is_finite() && *millis >= 0.0)
+                .map(|millis| Duration::from_secs_f64(millis / 1000.0))
+                .ok_or_else(|| invalid("expected milliseconds"))
+        };
+        let chance = || {
+            value
+                .parse::<f64>()
+                .ok()
+                .filter(|p| (0.0..=1.0).contains(p))
+                .ok_or_else(|| invalid("expected a chance from 0 to 1"))
+        };
+
+        match key {
+            "delay" => self.delay = millis()?,
+            "jitter" => self.jitter = millis()?,
+            "loss" => self.loss = chance()?,
+            "duplicate" => self.duplicate = chance()?,
+            "reorder" => self.reorder = chance()?,
+            "reorder_delay" => self.reorder_delay = millis()?,
+            "bandwidth" => {
+                let kbits: u64 = value.parse().map_err(|_| invalid("expected kbit/s"))?;
+                self.bandwidth = (kbits > 0).then_some(kbits * 1000 / 8);
+            }
+            _ => return Err(format!("unknown condition {key}")),
+        }
+        Ok(())
+    }
+}
+
+impl Default for Conditions {
+    /// A perfect link.
+    fn default() -> Self {
+        Self {
+            delay: Duration::ZERO,
+            jitter: Duration::ZERO,
+            loss: 0.0,
+            duplicate: 0.0,
+            reorder: 0.0,
+            reorder_delay: REORDER_DELAY,
+            bandwidth: None,
+        }
+    }
+}
+
+/// One direction of a simulated network path.
+#[derive(Debug)]
+pub struct Link {
+    conditions: Conditions,
+    rng: Rng,
+    /// When the datagrams already on their way are through the bandwidth cap.
+    busy_until: Option<Instant>,
+}
+
+impl Link {
+    pub fn new(conditions: Conditions, seed: u64) -> Self {
+        Self {
+            conditions,
+            rng: Rng::new(seed),
+            busy_until: None,
+        }
+    }
+
+    /// When each copy of a datagram of `len` bytes sent at `now` arrives. Empty when it is lost.
+    pub fn schedule(&mut self, len: usize, now: Instant) -> Vec<Instant> {
+        let conditions = self.conditions;
+        if self.rng.chance(conditions.loss) {
+            return Vec::new();
+        }
+
+        let mut sent = now;
+        if let Some(bandwidth) = conditions.bandwidth {
+            let start = self.busy_until.map_or(now, |busy| busy.max(now));
+            if start - now > MAX_QUEUE_DELAY {
+                return Vec::new();
+            }
+            sent = start + Duration::from_secs_f64(len as f64 / bandwidth as f64);
+            self.busy_until = Some(sent);
+        }
+
+        let copies = if self.rng.chance(conditions.duplicate) {
+            2
+        } else {
+            1
+        };
+        (0..copies)
+            .map(|_| {
+                let mut arrives =
+                    sent + conditions.delay + conditions.jitter.mul_f64(self.rng.next_f64());
+                if self.rng.chance(conditions.reorder) {
+                    arrives += conditions.reorder_delay;
+                }
+                arrives
+            })
+            .collect()
+    }
+}
+
+/// Conditions for both directions, as seen from the client.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct NetsimConfig {
+    /// Client to server.
+    pub up: Conditions,
+    /// Server to client.
+    pub down: Conditions,
+    /// Seeds every link, so runs with the same traffic lose the same datagrams.
+    pub seed: u64,
+}
+
+type Delivery = UnboundedSender<(Instant, Vec<u8>)>;
+
+/// Sends datagrams from `socket` to `target` at the times they were scheduled for, in order of
+/// those times. Stops once the sender is dropped and everything queued is sent.
+fn deliver(socket: Arc<UdpSocket>, target: SocketAddr) -> Delivery {
+    let (tx, mut rx) = unbounded_channel();
+    tokio::spawn(async move {
+        let mut queue: BinaryHeap<Reverse<(Instant, u64, Vec<u8>)>> = BinaryHeap::new();
+        // Breaks ties between datagrams due at the same time by the order they were sent in.
+        let mut sequence: u64 = 0;
+        let mut open = true;
+
+        loop {
+            let next = queue.peek().map(|Reverse((at, _, _))| *at);
+            if !open && next.is_none() {
+                return;
+            }
+
+            tokio::select! {
+                received = rx.recv(), if open => match received {
+                    Some((at, datagram)) => {
+                        queue.push(Reverse((at, sequence, datagram)));
+                        sequence += 1;
+                    }
+                    None => open = false,
+                },
+                _ = wait_until(next) => {
+                    let Reverse((_, _, datagram)) = queue.pop().unwrap();
+                    if let Err(e) = socket.send_to(&datagram, target).await {
+                        debug!(%target, error = %e, "netsim send failed");
+                    }
+                }
+            }
+        }
+    });
+    tx
+}
+
+async fn wait_until(deadline: Option<Instant>) {
+    match deadline {
+        Some(deadline) => sleep_until(deadline).await,
+        None => std::future::pending().await,
+    }
+}
+
+/// One client going through the proxy, with a socket of its own towards the server so the server
+/// can tell clients apart.
+struct Session {
+    link: Link,
+    upstream: Delivery,
+    reader: JoinHandle<()>,
+}
+
+impl Session {
+    async fn start(
+        client: SocketAddr,
+        server: SocketAddr,
+        listener: Arc<UdpSocket>,
+        config: &NetsimConfig,
+        index: u64,
+    ) -> io::Result<Session> {
+        let bind: SocketAddr = match server {
+            SocketAddr::V4(_) => "0.0.0.0:0".parse().unwrap(),
+            SocketAddr::V6(_) => "[::]:0".parse().unwrap(),
+        };
+        let socket = Arc::new(UdpSocket::bind(bind).await?);
+        info!(%client, via = ?socket.local_addr(), "new netsim session");
+
+        let upstream = deliver(socket.clone(), server);
+        let downstream = deliver(listener, client);
+        let mut link = Link::new(config.down, config.seed.wrapping_add(index * 2 + 1));
+        let reader = tokio::spawn(async move {
+            let mut buf = [0; MAX_DATAGRAM];
+            loop {
+                let (n, origin) = match socket.recv_from(&mut buf).await {
+                    Ok(received) => received,
+                    Err(e) => {
+                        debug!(%client, error = %e, "netsim receive failed");
+                        continue;
+                    }
+                };
+                if origin != server {
+                    continue;
+                }
+                for at in link.schedule(n, Instant::now()) {
+                    let _ = downstream.send((at, buf[..n].to_vec()));
+                }
+            }
+        });
+
+        Ok(Session {
+            link: Link::new(config.up, config.seed.wrapping_add(index * 2)),
+            upstream,
+            reader,
+        })
+    }
+}
+
+impl Drop for Session {
+    fn drop(&mut self) {
+        self.reader.abort();
+    }
+}
+
+/// Forwards UDP between clients and a server, delaying, dropping, duplicating and reordering
+/// datagrams on the way as `NetsimConfig` says.
+pub struct Proxy {
+    socket: Arc<UdpSocket>,
+    server: SocketAddr,
+    config: NetsimConfig,
+}
+
+impl Proxy {
+    /// Listens for clients on `addr` and forwards them to `server`.
+    pub async fn bind(
+        addr: SocketAddr,
+        server: SocketAddr,
+        config: NetsimConfig,
+    ) -> io::Result<Self> {
+        Ok(Self {
+            socket: Arc::new(UdpSocket::bind(addr).await?),
+            server,
+            config,
+        })
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.socket.local_addr()
+    }
+
+    /// Runs until the listening socket fails.
+    pub async fn run(self) -> io::Result<()> {
+        let mut sessions: HashMap<SocketAddr, Session> = HashMap::new();
+        let mut buf = [0; MAX_DATAGRAM];
+
+        loop {
+            let (n, client) = self.socket.recv_from(&mut buf).await?;
+            let index = sessions.len() as u64;
+            let session = match sessions.entry(client) {
+                Entry::Occupied(entry) => entry.into_mut(),
+                Entry::Vacant(entry) => {
+                    let session = Session::start(
+                        client,
+                        self.server,
+                        self.socket.clone(),
+                        &self.config,
+                        index,
+                    )
+                    .await?;
+                    entry.insert(session)
+                }
+            };
+
+            for at in session.link.schedule(n, Instant::now()) {
+                let _ = session.upstream.send((at, buf[..n].to_vec()));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    mod link {
+        use crate::netsim::{Conditions, Link};
+        use std::time::Duration;
+        use tokio::time::Instant;
+
+        fn ms(millis: u64) -> Duration {
+            Duration::from_millis(millis)
+        }
+
+        #[test]
+        fn perfect_link_delivers_right_away() {
+            let mut link = Link::new(Conditions::default(), 0);
+            let now = Instant::now();
+            assert_eq!(link.schedule(100, now), vec![now]);
+        }
+
+        #[test]
+        fn delay_and_jitter_stay_in_bounds() {
+            let conditions = Conditions {
+                delay: ms(50),
+                jitter: ms(10),
+                ..Default::default()
+            };
+            let mut link = Link::new(conditions, 1);
+            let now = Instant::now();
+            let arrivals: Vec<Instant> = (0..100).flat_map(|_| link.schedule(10, now)).collect();
+
+            assert_eq!(arrivals.len(), 100);
+            assert!(arrivals
+                .iter()
+                .all(|at| *at >= now + ms(50) && *at < now + ms(60)));
+            assert!(arrivals.iter().any(|at| *at != arrivals[0]));
+        }
+
+        #[test]
+        fn loses_and_duplicates() {
+            let now = Instant::now();
+            let mut lossy = Link::new(
+                Conditions {
+                    loss: 1.0,
+                    ..Default::default()
+                },
+                2,
+            );
+            assert!(lossy.schedule(10, now).is_empty());
+
+            let mut doubling = Link::new(
+                Conditions {
+                    duplicate: 1.0,
+                    ..Default::default()
+                },
+                2,
+            );
+            assert_eq!(doubling.schedule(10, now), vec![now, now]);
+        }
+
+        #[test]
+        fn reordered_datagrams_are_held_back() {
+            let conditions = Conditions {
+                reorder: 1.0,
+                reorder_delay: ms(30),
+                ..Default::default()
+            };
+            let now = Instant::now();
+            assert_eq!(
+                Link::new(conditions, 3).schedule(10, now),
+                vec![now + ms(30)]
+            );
+        }
+
+        #[test]
+        fn bandwidth_queues_then_drops() {
+            let conditions = Conditions {
+                bandwidth: Some(1000),
+                ..Default::default()
+            };
+            let mut link = Link::new(conditions, 4);
+            let now = Instant::now();
+
+            assert_eq!(link.schedule(100, now), vec![now + ms(100)]);
+            assert_eq!(link.schedule(100, now), vec![now + ms(200)]);
+            // A second later the queue has drained.
+            let later = now + ms(1000);
+            assert_eq!(link.schedule(500, later), vec![later + ms(500)]);
+
+            // More than a second of backlog is dropped.
+            link.schedule(600, later);
+            assert!(link.schedule(1, later).is_empty());
+        }
+
+        #[test]
+        fn sets_conditions_from_flags() {
+            let mut conditions = Conditions::default();
+            conditions.set("delay", "12.5").unwrap();
+            conditions.set("loss", "0.1").unwrap();
+            conditions.set("bandwidth", "64").unwrap();
+            assert_eq!(conditions.delay, Duration::from_micros(12_500));
+            assert_eq!(conditions.loss, 0.1);
+            assert_eq!(conditions.bandwidth, Some(8000));
+
+            conditions.set("bandwidth", "0").unwrap();
+            assert_eq!(conditions.bandwidth, None);
+            assert!(conditions.set("loss", "2").is_err());
+            assert!(conditions.set("delay", "-1").is_err());
+            assert!(conditions.set("latency", "1").is_err());
+        }
+    }
+
+    mod proxy {
+        use crate::config::Config;
+        use crate::engine::Message;
+        use crate::netsim::{Conditions, NetsimConfig, Proxy};
+        use crate::server::Server;
+        use net::frame::Frame;
+        use net::packet::join::Join;
+        use net::packet::movement::Movement;
+        use net::packet::Packet;
+        use std::sync::Arc;
+        use std::time::{Duration, Instant};
+        use tokio::net::UdpSocket;
+        use tokio::sync::mpsc::channel;
+        use tokio::time::timeout;
+
+        async fn start(server: std::net::SocketAddr, config: NetsimConfig) -> UdpSocket {
+            let proxy = Proxy::bind("127.0.0.1:0".parse().unwrap(), server, config)
+                .await
+                .unwrap();
+            let client = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+            client.connect(proxy.local_addr().unwrap()).await.unwrap();
+            tokio::spawn(proxy.run());
+            client
+        }
+
+        #[tokio::test]
+        async fn delays_both_directions() {
+            let echo = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+            let server = echo.local_addr().unwrap();
+            tokio::spawn(async move {
+                let mut buf = [0; 64];
+                while let Ok((n, origin)) = echo.recv_from(&mut buf).await {
+                    let _ = echo.send_to(&buf[..n], origin).await;
+                }
+            });
+
+            let delay = |millis| Conditions {
+                delay: Duration::from_millis(millis),
+                ..Default::default()
+            };
+            let config = NetsimConfig {
+                up: delay(20),
+                down: delay(30),
+                seed: 0,
+            };
+            let client = start(server, config).await;
+
+            let sent = Instant::now();
+            client.send(b"hello").await.unwrap();
+            let mut buf = [0; 64];
+            let n = timeout(Duration::from_secs(1), client.recv(&mut buf))
+                .await
+                .unwrap()
+                .unwrap();
+            assert_eq!(&buf[..n], b"hello");
+            assert!(sent.elapsed() >= Duration::from_millis(50));
+        }
+
+        /// Duplicated and overtaken frames never reach the engine, and the ones that do arrive in
+        /// the order they were sent.
+        #[tokio::test]
+        async fn server_drops_stale_frames() {
+            let config = Config {
+                addr: "127.0.0.1:0".parse().unwrap(),
+                ..Default::default()
+            };
+            let (engine_tx, mut engine_rx) = channel(config.engine_queue_capacity);
+            let mut server = Server::from_config(config).await;
+            server.engine_tx = Some(Arc::new(engine_tx));
+            let addr = server.local_addr().unwrap();
+            tokio::spawn(async move { server.run().await });
+
+            let netsim = NetsimConfig {
+                up: Conditions {
+                    duplicate: 0.3,
+                    reorder: 0.5,
+                    reorder_delay: Duration::from_millis(20),
+                    ..Default::default()
+                },
+                ..Default::default()
+            };
+            let client = start(addr, netsim).await;
+
+            let mut join = Frame::new();
+            join.packet = Some(Packet::Join(Join::default()));
+            client.send(&join.to_bytes()).await.unwrap();
+            let message = timeout(Duration::from_secs(1), engine_rx.recv()).await;
+            assert!(matches!(message, Ok(Some(Message::Join { .. }))));
+
+            // Each frame carries its sequence number in the keys it holds, since the server
+            // passes on nothing but movement, with the id of the player it gave out.
+            const FRAMES: u32 = 16;
+            for sequence in 0..FRAMES {
+                let mut frame = Frame::new();
+                frame.syn = sequence + 1;
+                frame.packet = Some(Packet::Movement(Movement {
+                    up: sequence & 1 != 0,
+                    down: sequence & 2 != 0,
+                    left: sequence & 4 != 0,
+                    right: sequence & 8 != 0,
+                    ..Default::default()
+                }));
+                client.send(&frame.to_bytes()).await.unwrap();
+                tokio::time::sleep(Duration::from_millis(1)).await;
+            }
+
+            let mut received = Vec::new();
+            while let Ok(Some(message)) =
+                timeout(Duration::from_millis(200), engine_rx.recv()).await
+            {
+                if let Message::Packet(Packet::Movement(movement)) = message {
+                    received.push(
+                        movement.up as u32
+                            | (movement.down as u32) << 1
+                            | (movement.left as u32) << 2
+                            | (movement.right as u32) << 3,
+                    );
+                }
+            }
+
+            assert!(!received.is_empty());
+            assert!(received.len() < FRAMES as usize, "{received:?}");
+            assert!(
+                received.windows(2).all(|pair| pair[0] < pair[1]),
+                "{received:?}"
+            );
+        }
+    }
+}
@@ -0,0 +1,246 @@
+use std::collections::VecDeque;
+use std::fmt::{Display, Formatter};
+use std::time::{Duration, Instant};
+use tracing::{info, warn};
+
+/// Ticks kept for the rolling percentiles.
+pub const PROFILE_WINDOW: usize = 256;
+/// How often the rolling percentiles are logged.
+pub const REPORT_INTERVAL: Duration = Duration::from_secs(10);
+
+/// The parts an engine update is timed in.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Phase {
+    /// Taking packets off the engine queue and applying them to players, including the ones
+    /// handled between ticks, which count towards the next update.
+    Input,
+    Players,
+    Entities,
+    /// Building the snapshot the server sends out.
+    Snapshot,
+    /// Handing the snapshot over to the server.
+    Send,
+}
+
+impl Phase {
+    pub const ALL: [Phase; 5] = [
+        Phase::Input,
+        Phase::Players,
+        Phase::Entities,
+        Phase::Snapshot,
+        Phase::Send,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Phase::Input => "input",
+            Phase::Players => "players",
+            Phase::Entities => "entities",
+            Phase::Snapshot => "snapshot",
+            Phase::Send => "send",
+        }
+    }
+}
+
+/// Time spent in each phase of one engine update.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct TickTimings {
+    phases: [Duration; Phase::ALL.len()],
+}
+
+impl TickTimings {
+    pub fn get(&self, phase: Phase) -> Duration {
+        self.phases[phase as usize]
+    }
+
+    pub fn add(&mut self, phase: Phase, duration: Duration) {
+        self.phases[phase as usize] += duration;
+    }
+
+    pub fn total(&self) -> Duration {
+        self.phases.iter().sum()
+    }
+}
+
+impl Display for TickTimings {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        for (i, phase) in Phase::ALL.into_iter().enumerate() {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{} {:?}", phase.name(), self.get(phase))?;
+        }
+        Ok(())
+    }
+}
+
+/// Rolling percentiles over the last `PROFILE_WINDOW` updates.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct TickReport {
+    pub samples: usize,
+    pub p50: Duration,
+    pub p99: Duration,
+    pub max: Duration,
+    /// p99 of each phase on its own, so the phases do not add up to `p99`.
+    pub p99_phases: TickTimings,
+}
+
+/// Times the phases of each engine update and warns when one does not fit in its budget.
+#[derive(Debug)]
+pub struct TickProfiler {
+    current: TickTimings,
+    window: VecDeque<TickTimings>,
+    last_report: Option<Instant>,
+    pub overruns: u64,
+}
+
+impl TickProfiler {
+    pub fn new() -> Self {
+        Self {
+            current: TickTimings::default(),
+            window: VecDeque::with_capacity(PROFILE_WINDOW),
+            last_report: None,
+            overruns: 0,
+        }
+    }
+
+    /// Adds the time since `started` to `phase` of the update in progress.
+    pub fn record(&mut self, phase: Phase, started: Instant) {
+        self.current.add(phase, started.elapsed());
+    }
+
+    /// Ends the update in progress and returns its timings, warning if they add up to more than
+    /// `budget`.
+    pub fn finish(&mut self, tick: u64, budget: Duration) -> TickTimings {
+        let timings = std::mem::take(&mut self.current);
+        let took = timings.total();
+        if took > budget {
+            self.overruns += 1;
+            warn!(tick, ?took, ?budget, breakdown = %timings, "tick over budget");
+        }
+
+        if self.window.len() == PROFILE_WINDOW {
+            self.window.pop_front();
+        }
+        self.window.push_back(timings);
+
+        let now = Instant::now();
+        let last_report = *self.last_report.get_or_insert(now);
+        if now.duration_since(last_report) >= REPORT_INTERVAL {
+            self.last_report = Some(now);
+            if let Some(report) = self.report() {
+                info!(
+                    samples = report.samples,
+                    p50 = ?report.p50,
+                    p99 = ?report.p99,
+                    max = ?report.max,
+                    p99_phases = %report.p99_phases,
+                    "tick timings"
+                );
+            }
+        }
+
+        timings
+    }
+
+    pub fn report(&self) -> Option<TickReport> {
+        if self.window.is_empty() {
+            return None;
+        }
+
+        let totals: Vec<Duration> = self.window.iter().map(TickTimings::total).collect();
+        let mut p99_phases = TickTimings::default();
+        for phase in Phase::ALL {
+            let durations: Vec<Duration> = self.window.iter().map(|t| t.get(phase)).collect();
+            p99_phases.add(phase, percentile(durations, 0.99));
+        }
+
+        Some(TickReport {
+            samples: totals.len(),
+            p50: percentile(totals.clone(), 0.5),
+            p99: percentile(totals.clone(), 0.99),
+            max: totals.into_iter().max().unwrap_or_default(),
+            p99_phases,
+        })
+    }
+}
+
+impl Default for TickProfiler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Nearest rank percentile, `samples` must not be empty.
+fn percentile(mut samples: Vec<Duration>, p: f64) -> Duration {
+    samples.sort_unstable();
+    let rank = (p * samples.len() as f64).ceil() as usize;
+    samples[rank.clamp(1, samples.len()) - 1]
+}
+
+#[cfg(test)]
+mod tests {
+    mod behavior {
+        use crate::profile::{percentile, Phase, TickProfiler, TickTimings};
+        use std::time::Duration;
+
+        fn timings(players: u64, entities: u64) -> TickTimings {
+            let mut timings = TickTimings::default();
+            timings.add(Phase::Players, Duration::from_millis(players));
+            timings.add(Phase::Entities, Duration::from_millis(entities));
+            timings
+        }
+
+        #[test]
+        fn percentiles_use_nearest_rank() {
+            let samples: Vec<Duration> = (1..=100).map(Duration::from_millis).collect();
+            assert_eq!(percentile(samples.clone(), 0.5), Duration::from_millis(50));
+            assert_eq!(percentile(samples.clone(), 0.99), Duration::from_millis(99));
+            assert_eq!(percentile(samples, 1.0), Duration::from_millis(100));
+            assert_eq!(
+                percentile(vec![Duration::from_millis(7)], 0.99),
+                Duration::from_millis(7)
+            );
+        }
+
+        #[test]
+        fn report_covers_the_window() {
+            let mut profiler = TickProfiler::new();
+            assert!(profiler.report().is_none());
+
+            for i in 1..=10 {
+                profiler.current = timings(i, 1);
+                profiler.finish(i, Duration::from_secs(1));
+            }
+
+            let report = profiler.report().unwrap();
+            assert_eq!(report.samples, 10);
+            assert_eq!(report.p50, Duration::from_millis(6));
+            assert_eq!(report.max, Duration::from_millis(11));
+            assert_eq!(
+                report.p99_phases.get(Phase::Players),
+                Duration::from_millis(10)
+            );
+            assert_eq!(
+                report.p99_phases.get(Phase::Entities),
+                Duration::from_millis(1)
+            );
+            assert_eq!(profiler.overruns, 0);
+        }
+
+        #[test]
+        fn counts_updates_over_budget() {
+            let mut profiler = TickProfiler::new();
+
+            profiler.current = timings(40, 20);
+            let finished = profiler.finish(1, Duration::from_millis(50));
+            assert_eq!(finished.total(), Duration::from_millis(60));
+            assert_eq!(profiler.overruns, 1);
+
+            profiler.current = timings(40, 20);
+            profiler.finish(2, Duration::from_millis(100));
+            assert_eq!(profiler.overruns, 1);
+            assert_eq!(profiler.current, TickTimings::default());
+        }
+    }
+}
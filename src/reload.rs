@@ -0,0 +1,146 @@
+use crate::admin::AdminCommand;
+use crate::config::Config;
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+use tokio::sync::mpsc::{Receiver, Sender};
+use tokio::time::interval;
+use tracing::{debug, info, warn};
+
+/// How often the config file is checked for changes.
+pub const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Loads the config for `args` again whenever its file changes or something arrives on
+/// `reload_rx`, and hands the tunables in it to the engine. Everything else in the config only
+/// takes effect on restart. Returns once the engine stops taking commands.
+pub async fn watch_config(
+    args: Vec<String>,
+    poll: Duration,
+    reload_rx: Receiver<()>,
+    admin_tx: Sender<AdminCommand>,
+) {
+    let mut reload_rx = Some(reload_rx);
+    let path = Config::path(args.clone());
+    let mut last_modified = path.as_deref().and_then(modified);
+    let mut poll = interval(poll);
+
+    loop {
+        tokio::select! {
+            _ = poll.tick() => {
+                let current = path.as_deref().and_then(modified);
+                if current == last_modified {
+                    continue;
+                }
+                last_modified = current;
+                debug!(?path, "config file changed");
+            }
+            request = recv(&mut reload_rx) => {
+                if request.is_none() {
+                    // Nobody can ask any more, keep watching the file.
+                    reload_rx = None;
+                    continue;
+                }
+            }
+        }
+
+        match Config::load(args.clone()) {
+            Ok(config) => {
+                info!(?path, "reloaded tunables");
+                if admin_tx
+                    .send(AdminCommand::SetTunables(config.tunables))
+                    .await
+                    .is_err()
+                {
+                    return;
+                }
+            }
+            Err(e) => warn!(error = %e, "keeping current tunables"),
+        }
+    }
+}
+
+async fn recv<T>(rx: &mut Option<Receiver<T>>) -> Option<T> {
+    match rx {
+        Some(rx) => rx.recv().await,
+        None => std::future::pending().await,
+    }
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    mod behavior {
+        use crate::admin::AdminCommand;
+        use crate::reload::watch_config;
+        use std::path::PathBuf;
+        use std::time::Duration;
+        use tokio::sync::mpsc::channel;
+        use tokio::time::timeout;
+
+        fn config_file(name: &str, text: &str) -> PathBuf {
+            let path = std::env::temp_dir().join(format!("{name}-{}.toml", std::process::id()));
+            std::fs::write(&path, text).unwrap();
+            path
+        }
+
+        #[tokio::test]
+        async fn reloads_when_asked_and_when_the_file_changes() {
+            let path = config_file("reload", "max_velocity = 5\n");
+            let args = vec!["--config".to_string(), path.display().to_string()];
+            let (reload_tx, reload_rx) = channel(1);
+            let (admin_tx, mut admin_rx) = channel(4);
+            tokio::spawn(watch_config(
+                args,
+                Duration::from_millis(10),
+                reload_rx,
+                admin_tx,
+            ));
+
+            reload_tx.send(()).await.unwrap();
+            let command = timeout(Duration::from_secs(1), admin_rx.recv()).await.unwrap();
+            let Some(AdminCommand::SetTunables(tunables)) = command else {
+                panic!("Expected tunables, got {command:?}");
+            };
+            assert_eq!(tunables.max_velocity, 5.0);
+
+            // Make sure the modification time moves even on coarse file systems.
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            std::fs::write(&path, "max_velocity = 7\nfriction = 1\n").unwrap();
+            let file = std::fs::File::options().write(true).open(&path).unwrap();
+            file.set_modified(std::time::SystemTime::now() + Duration::from_secs(1))
+                .unwrap();
+
+            let command = timeout(Duration::from_secs(1), admin_rx.recv()).await.unwrap();
+            let Some(AdminCommand::SetTunables(tunables)) = command else {
+                panic!("Expected tunables, got {command:?}");
+            };
+            assert_eq!(tunables.max_velocity, 7.0);
+            assert_eq!(tunables.friction, 1.0);
+
+            std::fs::remove_file(path).unwrap();
+        }
+
+        #[tokio::test]
+        async fn keeps_tunables_when_the_file_is_invalid() {
+            let path = config_file("reload-invalid", "max_velocity = -1\n");
+            let args = vec!["--config".to_string(), path.display().to_string()];
+            let (reload_tx, reload_rx) = channel(1);
+            let (admin_tx, mut admin_rx) = channel(4);
+            tokio::spawn(watch_config(
+                args,
+                Duration::from_secs(60),
+                reload_rx,
+                admin_tx,
+            ));
+
+            reload_tx.send(()).await.unwrap();
+            assert!(timeout(Duration::from_millis(50), admin_rx.recv())
+                .await
+                .is_err());
+
+            std::fs::remove_file(path).unwrap();
+        }
+    }
+}
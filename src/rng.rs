@@ -0,0 +1,54 @@
+/// xorshift64*, good enough for load tests and simulated networks and needs no dependency. The
+/// same seed always gives the same numbers.
+#[derive(Clone, Debug)]
+pub struct Rng(u64);
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        // The state must never be zero.
+        Self(seed.wrapping_mul(0x9e37_79b9_7f4a_7c15) | 1)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+
+    /// Uniform in `0.0..1.0`.
+    pub fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    /// True with probability `p`.
+    pub fn chance(&mut self, p: f64) -> bool {
+        self.next_f64() < p
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    mod behavior {
+        use crate::rng::Rng;
+
+        #[test]
+        fn same_seed_same_numbers() {
+            let (mut a, mut b) = (Rng::new(3), Rng::new(3));
+            for _ in 0..10 {
+                assert_eq!(a.next_u64(), b.next_u64());
+            }
+            assert_ne!(Rng::new(3).next_u64(), Rng::new(4).next_u64());
+        }
+
+        #[test]
+        fn chance_follows_probability() {
+            let mut rng = Rng::new(0);
+            assert!(!(0..1000).any(|_| rng.chance(0.0)));
+            assert!((0..1000).all(|_| rng.chance(1.0)));
+
+            let hits = (0..10_000).filter(|_| rng.chance(0.25)).count();
+            assert!((2200..2800).contains(&hits), "{hits}");
+        }
+    }
+}
@@ -0,0 +1,708 @@
+use crate::backpressure::{EngineClosed, Outbox, OverloadPolicy};
+use crate::config::Config;
+use crate::engine::Message;
+use crate::metrics::Metrics;
+use net::frame::{Frame, FrameRef, MAX_DATAGRAM};
+use net::id::Id;
+use net::nickname::Nickname;
+use net::packet::disconnect::{Disconnect, Reason, DISCONNECT_REPEATS};
+use net::packet::join::Join;
+use net::packet::ping::Ping;
+use net::packet::snapshot::Snapshot;
+use net::packet::welcome::Welcome;
+use net::packet::{Packet, PacketRef};
+use std::collections::{HashMap, HashSet};
+use std::io;
+use std::net::SocketAddr;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::UdpSocket;
+use tokio::sync::mpsc::Sender;
+use tokio::sync::watch;
+use tokio::time::{interval, sleep_until, Instant};
+use tracing::{debug, debug_span, info, warn, Instrument};
+
+/// How often packets left over in connection outboxes are offered to the engine again.
+const FLUSH_INTERVAL: Duration = Duration::from_millis(5);
+/// Datagrams read in one go once the socket is readable, before timers get a chance to run.
+const RECV_BATCH: usize = 256;
+/// How often connections are checked for having timed out.
+const SWEEP_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Everything the server knows about one peer. Owned by the task running `Server::run`, so frames
+/// from the same peer are handled strictly in the order they arrive.
+#[derive(Debug)]
+pub struct Connection {
+    origin: SocketAddr,
+    sequence_number: u32,
+    acknowledgement_number: u32,
+    outbox: Outbox,
+    subscription: Option<Subscription>,
+    /// The player this peer controls, once it has joined.
+    player: Option<Id>,
+    /// Gets `player` back from another address, `0` until joined.
+    token: u64,
+    /// Timed out, with the player kept for a while in case the client comes back.
+    suspended: bool,
+    last_seen: Instant,
+    pub engine_tx: Option<Arc<Sender<Message>>>,
+}
+
+impl Connection {
+    pub fn new(origin: SocketAddr, outbox: Outbox) -> Self {
+        Self {
+            origin,
+            sequence_number: 0,
+            acknowledgement_number: 0,
+            outbox,
+            subscription: None,
+            player: None,
+            token: 0,
+            suspended: false,
+            last_seen: Instant::now(),
+            engine_tx: None,
+        }
+    }
+
+    pub fn origin(&self) -> SocketAddr {
+        self.origin
+    }
+
+    pub fn player(&self) -> Option<Id> {
+        self.player
+    }
+
+    pub fn handle_frame(&mut self, frame: Frame) -> Result<(), EngineClosed> {
+        let Some(tx) = &self.engine_tx else {
+            return Ok(());
+        };
+
+        let syn = frame.syn;
+        if syn < self.acknowledgement_number {
+            return Ok(());
+        }
+
+        self.acknowledgement_number = syn + 1;
+
+        // Joining is handled by the server, the engine hears about it as `Message::Join`, and
+        // nothing but movement is meant for the engine. Peers can only move the player they were
+        // given, and nothing before they have one.
+        if let Some(Packet::Movement(mut movement)) = frame.packet {
+            match self.player {
+                Some(id) => {
+                    movement.id = id;
+                    self.outbox.push(Packet::Movement(movement));
+                }
+                None => debug!(
+                    origin = %self.origin,
+                    id = movement.id.0,
+                    "movement before joining, dropped"
+                ),
+            }
+        }
+        self.outbox.flush(tx)
+    }
+
+    /// Gives the peer a new player for the engine to spawn, and the `token` to get it back with.
+    pub fn join(&mut self, id: Id, token: u64, nickname: Nickname) {
+        self.outbox.push(Message::Join { id, nickname });
+        self.player = Some(id);
+        self.token = token;
+    }
+
+    /// Ends the session: no more snapshots, and the engine is told the player left. Returns the
+    /// player, if the peer had joined.
+    pub fn leave(&mut self) -> Option<Id> {
+        self.subscription = None;
+        let id = self.player.take()?;
+        self.outbox.push(Message::Leave(id));
+        Some(id)
+    }
+
+    /// Stops snapshots and has the engine stop the player, which stays until the client comes back
+    /// with the session token or `leave` is called. Returns the player, if the peer had joined.
+    pub fn suspend(&mut self) -> Option<Id> {
+        let id = self.player?;
+        self.suspended = true;
+        self.subscription = None;
+        self.outbox.push(Message::Suspend(id));
+        Some(id)
+    }
+
+    /// Retries handing over packets that did not fit into the engine queue earlier.
+    pub fn flush(&mut self) -> Result<(), EngineClosed> {
+        match &self.engine_tx {
+            Some(tx) => self.outbox.flush(tx),
+            None => Ok(()),
+        }
+    }
+
+    /// Wraps `packet` in the next outgoing frame for this peer.
+    pub fn frame(&mut self, packet: Packet) -> Frame {
+        let mut frame = self.header();
+        frame.packet = Some(packet);
+        frame
+    }
+
+    /// Next outgoing frame for this peer, without a packet.
+    pub fn header(&mut self) -> Frame {
+        let mut frame = Frame::new();
+        frame.syn = self.sequence_number;
+        frame.ack = self.acknowledgement_number;
+        self.sequence_number = self.sequence_number.wrapping_add(1);
+        frame
+    }
+}
+
+/// When a client that has joined gets its next snapshot.
+#[derive(Debug)]
+pub struct Subscription {
+    snapshot_interval: Duration,
+    next_snapshot: Instant,
+}
+
+impl Subscription {
+    pub fn new(snapshot_rate: f32) -> Self {
+        Self {
+            snapshot_interval: Duration::from_secs_f32(1.0 / snapshot_rate),
+            next_snapshot: Instant::now(),
+        }
+    }
+
+    pub fn snapshot_rate(&self) -> f32 {
+        1.0 / self.snapshot_interval.as_secs_f32()
+    }
+
+    /// Moves the schedule on by one interval, or restarts it from `now` if it has fallen behind.
+    fn reschedule(&mut self, now: Instant) {
+        self.next_snapshot += self.snapshot_interval;
+        if self.next_snapshot <= now {
+            self.next_snapshot = now + self.snapshot_interval;
+        }
+    }
+}
+
+pub struct Server {
+    socket: UdpSocket,
+    tps: f32,
+    snapshot_rate: f32,
+    max_players: usize,
+    connection_timeout: Duration,
+    reconnect_grace: Duration,
+    connection_quota: usize,
+    overload_policy: OverloadPolicy,
+    pub metrics: Arc<Metrics>,
+    pub engine_tx: Option<Arc<Sender<Message>>>,
+    pub snapshot_rx: Option<watch::Receiver<Snapshot>>,
+    /// `run` says goodbye to every client and returns once this turns `true`.
+    pub shutdown_rx: Option<watch::Receiver<bool>>,
+}
+
+impl Server {
+    pub async fn new() -> Self {
+        Self::from_config(Config::default()).await
+    }
+
+    pub async fn from_config(config: Config) -> Self {
+        let socket = UdpSocket::bind(config.addr).await.unwrap();
+        info!(addr = %socket.local_addr().unwrap_or(config.addr), "listening");
+        Self {
+            socket,
+            tps: config.tps,
+            snapshot_rate: config.snapshot_rate,
+            max_players: config.max_players,
+            connection_timeout: config.connection_timeout,
+            reconnect_grace: config.reconnect_grace,
+            connection_quota: config.connection_quota,
+            overload_policy: config.overload_policy,
+            metrics: Arc::new(Metrics::default()),
+            engine_tx: None,
+            snapshot_rx: None,
+            shutdown_rx: None,
+        }
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.socket.local_addr()
+    }
+
+    /// Runs until the engine stops taking packets.
+    pub async fn run(&self) -> Result<(), EngineClosed> {
+        let mut connections: HashMap<SocketAddr, Connection> = HashMap::new();
+        let mut flush = interval(FLUSH_INTERVAL);
+        let mut sweep = interval(SWEEP_INTERVAL);
+        let mut buf = [0; MAX_DATAGRAM];
+        // Big enough for a snapshot that fills a datagram, so it never has to grow.
+        let mut send_buf = Vec::with_capacity(MAX_DATAGRAM);
+        let mut shutdown_rx = self.shutdown_rx.clone();
+
+        loop {
+            if self.engine_tx.as_ref().is_some_and(|tx| tx.is_closed()) {
+                return Err(EngineClosed);
+            }
+            self.metrics
+                .connections
+                .store(connections.len() as u64, Ordering::Relaxed);
+
+            let next_snapshot = match self.snapshot_rx {
+                Some(_) => connections
+                    .values()
+                    .filter_map(|connection| connection.subscription.as_ref())
+                    .map(|subscription| subscription.next_snapshot)
+                    .min(),
+                None => None,
+            };
+
+            tokio::select! {
+                result = self.socket.recv_from(&mut buf) => match result {
+                    Ok((n, origin)) => {
+                        self.handle_datagram(&mut connections, &buf[..n], origin, &mut send_buf)
+                            .instrument(debug_span!("connection", %origin))
+                            .await?;
+                        self.drain_socket(&mut connections, &mut buf, &mut send_buf).await?;
+                    }
+                    Err(e) => warn!(error = %e, "receive failed"),
+                },
+                _ = wait_until(next_snapshot) => {
+                    self.send_snapshots(&mut connections, &mut send_buf).await;
+                }
+                _ = flush.tick() => {
+                    for connection in connections.values_mut() {
+                        connection.flush()?;
+                    }
+                }
+                _ = sweep.tick() => self.sweep(&mut connections, &mut send_buf).await,
+                _ = shutdown(&mut shutdown_rx) => {
+                    return self.disconnect_all(connections, &mut send_buf).await;
+                }
+            }
+        }
+    }
+
+    /// Tells every client the server is going away and hands the engine everything it still
+    /// needs, ending with every player leaving. No datagram is read after this, so nobody can
+    /// join again.
+    async fn disconnect_all(
+        &self,
+        mut connections: HashMap<SocketAddr, Connection>,
+        send_buf: &mut Vec<u8>,
+    ) -> Result<(), EngineClosed> {
+        info!(connections = connections.len(), "disconnecting everyone");
+        let disconnect = Disconnect::new(Reason::ServerShutdown);
+        for connection in connections.values_mut() {
+            if connection.leave().is_some() {
+                self.send_disconnect(connection, &disconnect, send_buf)
+                    .await;
+            }
+        }
+
+        for connection in connections.values_mut() {
+            if let Some(tx) = &connection.engine_tx {
+                connection.outbox.close(tx).await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Handles datagrams that are already waiting on the socket without going back through
+    /// `select!` for each one.
+    async fn drain_socket(
+        &self,
+        connections: &mut HashMap<SocketAddr, Connection>,
+        buf: &mut [u8],
+        send_buf: &mut Vec<u8>,
+    ) -> Result<(), EngineClosed> {
+        for _ in 0..RECV_BATCH {
+            match self.socket.try_recv_from(buf) {
+                Ok((n, origin)) => {
+                    self.handle_datagram(connections, &buf[..n], origin, send_buf)
+                        .instrument(debug_span!("connection", %origin))
+                        .await?
+                }
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                Err(e) => warn!(error = %e, "receive failed"),
+            }
+        }
+        Ok(())
+    }
+
+    async fn handle_datagram(
+        &self,
+        connections: &mut HashMap<SocketAddr, Connection>,
+        bytes: &[u8],
+        origin: SocketAddr,
+        send_buf: &mut Vec<u8>,
+    ) -> Result<(), EngineClosed> {
+        self.metrics.received(bytes);
+        let Some(frame) = FrameRef::from_bytes(bytes) else {
+            self.metrics.decode_errors.fetch_add(1, Ordering::Relaxed);
+            debug!(len = bytes.len(), "dropping malformed frame");
+            return Ok(());
+        };
+        if frame.packet.is_none() && bytes.len() > Frame::HEADER_SIZE {
+            self.metrics.decode_errors.fetch_add(1, Ordering::Relaxed);
+            debug!(len = bytes.len(), "dropping malformed packet");
+        }
+
+        // Nothing else in the frame can be trusted, so the peer is only told why it is ignored.
+        // The origin may be spoofed, so the reply is never bigger than what triggered it, or the
+        // server would amplify floods aimed at someone else.
+        if frame.version != Frame::PROTOCOL_VERSION {
+            debug!(version = frame.version, "wrong protocol version");
+            let mut reply = Frame::new();
+            reply.packet = Some(Packet::Disconnect(Disconnect::new(Reason::VersionMismatch)));
+            if bytes.len() >= reply.encoded_len() {
+                self.send_frame(&reply, origin, send_buf).await;
+            }
+            return Ok(());
+        }
+
+        if let Some(PacketRef::Disconnect(disconnect)) = frame.packet {
+            // Repeats of a goodbye that was already handled find no connection.
+            if let Some(connection) = connections.get_mut(&origin) {
+                if let Some(id) = connection.leave() {
+                    info!(
+                        id = id.0,
+                        reason = %disconnect.reason,
+                        message = disconnect.message,
+                        "left"
+                    );
+                }
+                connection.flush()?;
+                if connection.outbox.is_empty() {
+                    connections.remove(&origin);
+                }
+            }
+            return Ok(());
+        }
+
+        // A suspended player only comes back by joining again. Anything else from its address
+        // neither moves it nor keeps the session from expiring.
+        if !matches!(frame.packet, Some(PacketRef::Join(_)))
+            && connections
+                .get(&origin)
+                .is_some_and(|connection| connection.suspended)
+        {
+            return Ok(());
+        }
+
+        // Suspended players keep their slot.
+        let (joined, free_id) = match &frame.packet {
+            Some(PacketRef::Join(_)) => (
+                connections
+                    .values()
+                    .filter(|connection| connection.player.is_some())
+                    .count(),
+                free_id(connections),
+            ),
+            _ => (0, None),
+        };
+        let reclaimed = match &frame.packet {
+            Some(PacketRef::Join(join)) if join.token != 0 => {
+                self.reclaim(connections, join.token, origin, send_buf)
+                    .await
+            }
+            _ => None,
+        };
+
+        let connection = connections.entry(origin).or_insert_with(|| {
+            let outbox = Outbox::new(
+                self.connection_quota,
+                self.overload_policy,
+                self.metrics.overload.clone(),
+            );
+            info!("new connection");
+            let mut connection = Connection::new(origin, outbox);
+            connection.engine_tx = self.engine_tx.clone();
+            connection
+        });
+        connection.last_seen = Instant::now();
+
+        if let Some(PacketRef::Join(join)) = &frame.packet {
+            let id = match (connection.player, reclaimed, free_id) {
+                (Some(id), _, _) => id,
+                (None, Some(id), _) => {
+                    connection.player = Some(id);
+                    connection.token = join.token;
+                    id
+                }
+                (None, None, Some(id)) if joined < self.max_players => {
+                    let token = match session_token() {
+                        Ok(token) => token,
+                        Err(e) => {
+                            warn!(error = %e, "no session token to give, turning join away");
+                            return connection.handle_frame(frame.to_owned());
+                        }
+                    };
+                    connection.join(id, token, join.nickname);
+                    id
+                }
+                (None, _, _) => {
+                    info!(
+                        max_players = self.max_players,
+                        "server full, turning join away"
+                    );
+                    // Only once, the peer will ask again if it gets lost.
+                    let reply =
+                        connection.frame(Packet::Disconnect(Disconnect::new(Reason::RoomFull)));
+                    self.send_frame(&reply, origin, send_buf).await;
+                    return connection.handle_frame(frame.to_owned());
+                }
+            };
+            let subscription = self.subscribe(join);
+            let welcome = Welcome {
+                id,
+                tick_rate: self.tps,
+                snapshot_rate: subscription.snapshot_rate(),
+                token: connection.token,
+            };
+            info!(
+                id = id.0,
+                nickname = %join.nickname,
+                snapshot_rate = subscription.snapshot_rate(),
+                reclaimed = reclaimed.is_some(),
+                "joined"
+            );
+            connection.suspended = false;
+            connection.subscription = Some(subscription);
+            let frame = connection.frame(Packet::Welcome(welcome));
+            self.send_frame(&frame, origin, send_buf).await;
+        }
+
+        connection.handle_frame(frame.to_owned())?;
+
+        // Pings are answered straight away, acknowledging the ping, so clients can time the
+        // round trip.
+        if let Some(PacketRef::Ping(_)) = frame.packet {
+            let frame = connection.frame(Packet::Ping(Ping));
+            self.send_frame(&frame, origin, send_buf).await;
+        }
+        Ok(())
+    }
+
+    /// Suspends the players of connections that have not sent anything for `connection_timeout`,
+    /// and forgets those connections once `reconnect_grace` has passed as well and the engine has
+    /// been told to remove their player.
+    async fn sweep(
+        &self,
+        connections: &mut HashMap<SocketAddr, Connection>,
+        send_buf: &mut Vec<u8>,
+    ) {
+        let now = Instant::now();
+        let idle: Vec<SocketAddr> = connections
+            .iter()
+            .filter(|(_, connection)| {
+                now.saturating_duration_since(connection.last_seen) >= self.connection_timeout
+            })
+            .map(|(origin, _)| *origin)
+            .collect();
+
+        for origin in idle {
+            let Some(connection) = connections.get_mut(&origin) else {
+                continue;
+            };
+            let idle = now.saturating_duration_since(connection.last_seen);
+            if connection.suspended {
+                if idle < self.connection_timeout + self.reconnect_grace {
+                    continue;
+                }
+                if let Some(id) = connection.leave() {
+                    info!(%origin, id = id.0, ?idle, "session expired");
+                }
+            } else if !self.reconnect_grace.is_zero() && connection.player.is_some() {
+                if let Some(id) = connection.suspend() {
+                    info!(
+                        %origin,
+                        id = id.0,
+                        ?idle,
+                        grace = ?self.reconnect_grace,
+                        "connection lost, keeping the player"
+                    );
+                    let disconnect = Disconnect::with_message(
+                        Reason::Timeout,
+                        &format!(
+                            "join again within {:?} to keep playing",
+                            self.reconnect_grace
+                        ),
+                    );
+                    // Most likely nobody is listening, but the way back may be all that broke.
+                    self.send_disconnect(connection, &disconnect, send_buf)
+                        .await;
+                }
+                let _ = connection.flush();
+                continue;
+            } else if let Some(id) = connection.leave() {
+                info!(%origin, id = id.0, ?idle, "connection timed out");
+                self.send_disconnect(connection, &Disconnect::new(Reason::Timeout), send_buf)
+                    .await;
+            }
+            // A closed engine is noticed by `run`.
+            let _ = connection.flush();
+            if connection.outbox.is_empty() {
+                connections.remove(&origin);
+            }
+        }
+    }
+
+    /// Clients get snapshots at the rate they ask for in `join`, capped at the server's rate.
+    fn subscribe(&self, join: &Join) -> Subscription {
+        let requested = join.snapshot_rate as f32;
+        let snapshot_rate = if requested > 0.0 {
+            requested.min(self.snapshot_rate)
+        } else {
+            self.snapshot_rate
+        };
+        Subscription::new(snapshot_rate)
+    }
+
+    /// The snapshot is encoded once, only the frame header in front of it is rewritten for each
+    /// peer.
+    async fn send_snapshots(
+        &self,
+        connections: &mut HashMap<SocketAddr, Connection>,
+        send_buf: &mut Vec<u8>,
+    ) {
+        let Some(snapshot_rx) = &self.snapshot_rx else {
+            return;
+        };
+
+        send_buf.clear();
+        send_buf.resize(Frame::HEADER_SIZE, 0);
+        snapshot_rx.borrow().encode(send_buf);
+
+        let now = Instant::now();
+        for connection in connections.values_mut() {
+            let Some(subscription) = &mut connection.subscription else {
+                continue;
+            };
+            if subscription.next_snapshot > now {
+                continue;
+            }
+
+            subscription.reschedule(now);
+            connection
+                .header()
+                .encode_header(&mut &mut send_buf[..Frame::HEADER_SIZE]);
+            match self.socket.send_to(send_buf, connection.origin).await {
+                Ok(_) => self.metrics.sent(send_buf),
+                Err(e) => {
+                    warn!(origin = %connection.origin, error = %e, "sending snapshot failed")
+                }
+            }
+        }
+    }
+
+    /// Tells the peer why its session is over, `DISCONNECT_REPEATS` times since any one may get
+    /// lost.
+    /// Takes the player of session `token` away from the connection that has it, for a client
+    /// that joined again from `origin`. A connection that has not timed out is told it lost the
+    /// player, in case someone is still behind it.
+    async fn reclaim(
+        &self,
+        connections: &mut HashMap<SocketAddr, Connection>,
+        token: u64,
+        origin: SocketAddr,
+        send_buf: &mut Vec<u8>,
+    ) -> Option<Id> {
+        if connections
+            .get(&origin)
+            .is_some_and(|connection| connection.player.is_some())
+        {
+            return None;
+        }
+        let previous = connections
+            .iter()
+            .find(|(previous, connection)| {
+                **previous != origin
+                    && same_token(connection.token, token)
+                    && connection.player.is_some()
+            })
+            .map(|(previous, _)| *previous)?;
+
+        let mut connection = connections.remove(&previous)?;
+        let id = connection.player.take()?;
+        if !connection.suspended {
+            let disconnect =
+                Disconnect::with_message(Reason::Kicked, "session taken over elsewhere");
+            self.send_disconnect(&mut connection, &disconnect, send_buf)
+                .await;
+        }
+        // Whatever the engine has not taken yet goes now, since the connection will not be
+        // flushed again.
+        let _ = connection.flush();
+        info!(%previous, id = id.0, "session reclaimed");
+        Some(id)
+    }
+
+    async fn send_disconnect(
+        &self,
+        connection: &mut Connection,
+        disconnect: &Disconnect,
+        send_buf: &mut Vec<u8>,
+    ) {
+        for _ in 0..DISCONNECT_REPEATS {
+            let frame = connection.frame(Packet::Disconnect(disconnect.clone()));
+            self.send_frame(&frame, connection.origin, send_buf).await;
+        }
+    }
+
+    async fn send_frame(&self, frame: &Frame, target: SocketAddr, send_buf: &mut Vec<u8>) {
+        send_buf.clear();
+        frame.encode(send_buf);
+        match self.socket.send_to(send_buf, target).await {
+            Ok(_) => self.metrics.sent(send_buf),
+            Err(e) => warn!(%target, error = %e, "send failed"),
+        }
+    }
+}
+
+/// From the operating system's random source, since the token is all it takes to get a player
+/// back and so must not be guessable.
+fn session_token() -> Result<u64, getrandom::Error> {
+    loop {
+        let mut token = [0; 8];
+        getrandom::getrandom(&mut token)?;
+        let token = u64::from_ne_bytes(token);
+        if token != 0 {
+            return Ok(token);
+        }
+    }
+}
+
+/// Looks at every byte whatever the first difference, so how long it takes says nothing about
+/// how close a guess was.
+fn same_token(a: u64, b: u64) -> bool {
+    let difference = (a ^ b)
+        .to_ne_bytes()
+        .iter()
+        .fold(0, |difference, byte| difference | byte);
+    std::hint::black_box(difference) == 0
+}
+
+/// Lowest player id no connection has, `0` is never handed out.
+fn free_id(connections: &HashMap<SocketAddr, Connection>) -> Option<Id> {
+    let used: HashSet<u16> = connections
+        .values()
+        .filter_map(|connection| connection.player)
+        .map(|id| id.0)
+        .collect();
+    (1..=u16::MAX).find(|id| !used.contains(id)).map(Id)
+}
+
+/// Resolves once `rx` turns `true`, never without a receiver or once the sender is gone.
+async fn shutdown(rx: &mut Option<watch::Receiver<bool>>) {
+    let Some(rx) = rx else {
+        return std::future::pending().await;
+    };
+    if rx.wait_for(|&shutdown| shutdown).await.is_err() {
+        std::future::pending().await
+    }
+}
+
+async fn wait_until(deadline: Option<Instant>) {
+    match deadline {
+        Some(deadline) => sleep_until(deadline).await,
+        None => std::future::pending().await,
+    }
+}
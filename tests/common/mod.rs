@@ -0,0 +1,256 @@
+//! Boots a `Server` and an `Engine` on an ephemeral port and talks to them over real UDP, the way
+//! `bin/server.rs` wires them up.
+
+#![allow(dead_code)]
+
+use lib::config::Config;
+use lib::engine::Engine;
+use lib::metrics::Metrics;
+use lib::server::Server;
+use net::frame::{Frame, FrameRef, MAX_DATAGRAM};
+use net::id::Id;
+use net::packet::disconnect::Disconnect;
+use net::packet::join::Join;
+use net::packet::movement::Movement;
+use net::packet::snapshot::Snapshot;
+use net::packet::welcome::Welcome;
+use net::packet::Packet;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::UdpSocket;
+use tokio::sync::mpsc::channel;
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+use tokio::time::{timeout_at, Instant};
+
+/// How long any wait in a test may take before it fails. Generous, since the server only sweeps
+/// idle connections once a second.
+pub const TIMEOUT: Duration = Duration::from_secs(3);
+
+/// A server and engine running on their own tasks, stopped when dropped.
+pub struct TestServer {
+    pub addr: SocketAddr,
+    pub metrics: Arc<Metrics>,
+    /// What the engine published after its latest tick.
+    state: watch::Receiver<Snapshot>,
+    shutdown_tx: watch::Sender<bool>,
+    engine: JoinHandle<()>,
+    server: JoinHandle<()>,
+}
+
+impl TestServer {
+    pub async fn start() -> TestServer {
+        Self::with_config(Config::default()).await
+    }
+
+    /// Starts with `config`, on a free loopback port whatever its `addr`.
+    pub async fn with_config(config: Config) -> TestServer {
+        let config = Config {
+            addr: "127.0.0.1:0".parse().unwrap(),
+            metrics_addr: None,
+            ..config
+        };
+        let (engine_tx, server_rx) = channel(config.engine_queue_capacity);
+        let (snapshot_tx, snapshot_rx) = watch::channel(Snapshot::default());
+        let (shutdown_tx, shutdown_rx) = watch::channel(false);
+        let metrics = Arc::new(Metrics::default());
+        let mut engine = Engine::from_config(&config);
+        engine.server_rx = Some(server_rx);
+        engine.snapshot_tx = Some(snapshot_tx);
+        engine.metrics = metrics.clone();
+
+        let mut server = Server::from_config(config).await;
+        server.engine_tx = Some(Arc::new(engine_tx));
+        server.snapshot_rx = Some(snapshot_rx.clone());
+        server.metrics = metrics.clone();
+        server.shutdown_rx = Some(shutdown_rx);
+        let addr = server.local_addr().unwrap();
+
+        TestServer {
+            addr,
+            metrics,
+            state: snapshot_rx,
+            shutdown_tx,
+            engine: tokio::spawn(async move { engine.run().await }),
+            server: tokio::spawn(async move {
+                let _ = server.run().await;
+            }),
+        }
+    }
+
+    /// Binds a new client socket aimed at the server.
+    pub async fn client(&self) -> TestClient {
+        TestClient::connect(self.addr).await
+    }
+
+    /// The engine's latest state, as it would go out in a snapshot.
+    pub fn state(&self) -> Snapshot {
+        self.state.borrow().clone()
+    }
+
+    /// Waits for an engine tick whose state matches `predicate`, panicking after `TIMEOUT`.
+    pub async fn wait_for_state(&mut self, predicate: impl Fn(&Snapshot) -> bool) -> Snapshot {
+        let deadline = Instant::now() + TIMEOUT;
+        loop {
+            {
+                let state = self.state.borrow_and_update();
+                if predicate(&state) {
+                    return state.clone();
+                }
+            }
+            match timeout_at(deadline, self.state.changed()).await {
+                Ok(Ok(())) => {}
+                Ok(Err(_)) => panic!("engine stopped"),
+                Err(_) => panic!("engine state never matched, last {:?}", self.state()),
+            }
+        }
+    }
+
+    /// Shuts down the way a signal would and waits for the server and then the engine to stop,
+    /// panicking after `TIMEOUT`.
+    pub async fn shut_down(&mut self) {
+        self.shutdown_tx.send_replace(true);
+        let stopped = async {
+            (&mut self.server).await.unwrap();
+            (&mut self.engine).await.unwrap();
+        };
+        if tokio::time::timeout(TIMEOUT, stopped).await.is_err() {
+            panic!("server did not shut down");
+        }
+    }
+}
+
+impl Drop for TestServer {
+    fn drop(&mut self) {
+        self.engine.abort();
+        self.server.abort();
+    }
+}
+
+/// A scripted client, sending frames by hand and keeping whatever it receives.
+pub struct TestClient {
+    pub socket: UdpSocket,
+    pub id: Option<Id>,
+    /// Sent with every join, so set it to take over an earlier session.
+    pub token: u64,
+    syn: u32,
+    send_buf: Vec<u8>,
+}
+
+impl TestClient {
+    pub async fn connect(addr: SocketAddr) -> TestClient {
+        let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        socket.connect(addr).await.unwrap();
+        TestClient {
+            socket,
+            id: None,
+            token: 0,
+            syn: 0,
+            send_buf: Vec::with_capacity(64),
+        }
+    }
+
+    /// Sends `packet` in the next frame and returns its sequence number. A failed send counts as
+    /// a lost datagram.
+    pub async fn send(&mut self, packet: Packet) -> u32 {
+        let mut frame = Frame::new();
+        let syn = self.syn;
+        frame.syn = syn;
+        frame.packet = Some(packet);
+        self.syn = self.syn.wrapping_add(1);
+
+        self.send_buf.clear();
+        frame.encode(&mut self.send_buf);
+        let _ = self.socket.send(&self.send_buf).await;
+        syn
+    }
+
+    /// Waits for the next frame, or `None` once `deadline` passes. Receive errors, like a refused
+    /// connection once the server has gone, are skipped.
+    pub async fn recv_until(&mut self, deadline: Instant) -> Option<Frame> {
+        let mut buf = [0; MAX_DATAGRAM];
+        loop {
+            let Ok(n) = timeout_at(deadline, self.socket.recv(&mut buf))
+                .await
+                .ok()?
+            else {
+                continue;
+            };
+            if let Some(frame) = FrameRef::from_bytes(&buf[..n]) {
+                return Some(frame.to_owned());
+            }
+        }
+    }
+
+    /// Waits for the next packet matching `select`, skipping everything else, panicking after
+    /// `TIMEOUT`.
+    pub async fn expect<T>(&mut self, mut select: impl FnMut(Packet) -> Option<T>) -> T {
+        let deadline = Instant::now() + TIMEOUT;
+        loop {
+            let Some(frame) = self.recv_until(deadline).await else {
+                panic!("timed out waiting for a packet");
+            };
+            if let Some(found) = frame.packet.and_then(&mut select) {
+                return found;
+            }
+        }
+    }
+
+    /// Joins and waits for the `Welcome`, remembering the id and token it hands out.
+    pub async fn join(&mut self, nickname: &str) -> Welcome {
+        let mut join = Join::new(0, nickname);
+        join.token = self.token;
+        self.send(Packet::Join(join)).await;
+        let welcome = self
+            .expect(|packet| match packet {
+                Packet::Welcome(welcome) => Some(welcome),
+                _ => None,
+            })
+            .await;
+        self.id = Some(welcome.id);
+        self.token = welcome.token;
+        welcome
+    }
+
+    /// Sends the keys held right now, as `(up, down, left, right)`.
+    pub async fn hold(&mut self, (up, down, left, right): (bool, bool, bool, bool)) {
+        let id = self.id.expect("hold before join");
+        let movement = Movement {
+            id,
+            up,
+            down,
+            left,
+            right,
+        };
+        self.send(Packet::Movement(movement)).await;
+    }
+
+    /// Waits for the server to end the session, panicking after `TIMEOUT`.
+    pub async fn disconnected(&mut self) -> Disconnect {
+        self.expect(|packet| match packet {
+            Packet::Disconnect(disconnect) => Some(disconnect),
+            _ => None,
+        })
+        .await
+    }
+
+    /// Waits for a snapshot matching `predicate`, panicking after `TIMEOUT`.
+    pub async fn snapshot(&mut self, predicate: impl Fn(&Snapshot) -> bool) -> Snapshot {
+        self.expect(|packet| match packet {
+            Packet::Snapshot(snapshot) if predicate(&snapshot) => Some(snapshot),
+            _ => None,
+        })
+        .await
+    }
+
+    /// Collects every frame that arrives within `wait`.
+    pub async fn drain(&mut self, wait: Duration) -> Vec<Frame> {
+        let deadline = Instant::now() + wait;
+        let mut frames = Vec::new();
+        while let Some(frame) = self.recv_until(deadline).await {
+            frames.push(frame);
+        }
+        frames
+    }
+}
@@ -0,0 +1,325 @@
+//! Server and engine together, played through real sockets.
+
+mod common;
+
+use common::TestServer;
+use lib::config::Config;
+use net::frame::Frame;
+use net::id::Id;
+use net::packet::disconnect::{Disconnect, Reason, DISCONNECT_REPEATS};
+use net::packet::join::Join;
+use net::packet::ping::Ping;
+use net::packet::snapshot::Snapshot;
+use net::packet::Packet;
+use std::time::Duration;
+use tokio::time::Instant;
+
+fn has(snapshot: &Snapshot, id: Id) -> bool {
+    snapshot.players.iter().any(|player| player.id == id)
+}
+
+#[tokio::test]
+async fn joining_spawns_a_player() {
+    let mut server = TestServer::start().await;
+    let mut client = server.client().await;
+
+    let welcome = client.join("alice").await;
+    assert_eq!(welcome.tick_rate, Config::default().tps);
+
+    let snapshot = client.snapshot(|snapshot| has(snapshot, welcome.id)).await;
+    assert_eq!(snapshot.players.len(), 1);
+    server.wait_for_state(|state| has(state, welcome.id)).await;
+}
+
+#[tokio::test]
+async fn players_see_each_other() {
+    let server = TestServer::start().await;
+    let mut alice = server.client().await;
+    let mut bob = server.client().await;
+
+    let a = alice.join("alice").await.id;
+    let b = bob.join("bob").await.id;
+    assert_ne!(a, b);
+
+    alice
+        .snapshot(|snapshot| has(snapshot, a) && has(snapshot, b))
+        .await;
+    bob.snapshot(|snapshot| has(snapshot, a) && has(snapshot, b))
+        .await;
+}
+
+#[tokio::test]
+async fn held_keys_move_the_player() {
+    let server = TestServer::start().await;
+    let mut client = server.client().await;
+    let id = client.join("alice").await.id;
+    let position = |snapshot: &Snapshot| {
+        snapshot
+            .players
+            .iter()
+            .find(|player| player.id == id)
+            .map(|player| player.position)
+    };
+    let start = client
+        .snapshot(|snapshot| position(snapshot).is_some())
+        .await;
+    let start = position(&start).unwrap();
+
+    for _ in 0..10 {
+        client.hold((true, false, false, true)).await;
+        tokio::time::sleep(Duration::from_millis(30)).await;
+    }
+
+    client
+        .snapshot(|snapshot| {
+            position(snapshot).is_some_and(|now| now.x > start.x && now.y > start.y)
+        })
+        .await;
+}
+
+#[tokio::test]
+async fn peers_that_have_not_joined_can_not_move_anyone() {
+    let mut server = TestServer::start().await;
+    let mut alice = server.client().await;
+    let id = alice.join("alice").await.id;
+    let start = server.wait_for_state(|state| has(state, id)).await;
+
+    let mut stranger = server.client().await;
+    stranger.id = Some(id);
+    for _ in 0..10 {
+        stranger.hold((true, false, false, true)).await;
+        tokio::time::sleep(Duration::from_millis(30)).await;
+    }
+
+    let tick = server.state().tick;
+    let now = server.wait_for_state(|state| state.tick > tick).await;
+    assert_eq!(now.players, start.players);
+}
+
+#[tokio::test]
+async fn pings_are_answered() {
+    let server = TestServer::start().await;
+    let mut client = server.client().await;
+    client.join("alice").await;
+
+    let syn = client.send(Packet::Ping(Ping)).await;
+    let deadline = Instant::now() + common::TIMEOUT;
+    while let Some(frame) = client.recv_until(deadline).await {
+        if let Some(Packet::Ping(_)) = frame.packet {
+            assert_eq!(frame.ack, syn.wrapping_add(1));
+            return;
+        }
+    }
+    panic!("ping never answered");
+}
+
+#[tokio::test]
+async fn idle_players_are_removed() {
+    let mut server = TestServer::with_config(Config {
+        connection_timeout: Duration::from_millis(200),
+        reconnect_grace: Duration::ZERO,
+        ..Default::default()
+    })
+    .await;
+    let mut client = server.client().await;
+    let id = client.join("alice").await.id;
+
+    server.wait_for_state(|state| has(state, id)).await;
+    server.wait_for_state(|state| !has(state, id)).await;
+    assert_eq!(client.disconnected().await.reason, Reason::Timeout);
+}
+
+#[tokio::test]
+async fn reconnecting_takes_the_player_back() {
+    let mut server = TestServer::with_config(Config {
+        connection_timeout: Duration::from_millis(200),
+        reconnect_grace: Duration::from_secs(10),
+        ..Default::default()
+    })
+    .await;
+    let mut client = server.client().await;
+    let welcome = client.join("alice").await;
+    assert_ne!(welcome.token, 0);
+    server.wait_for_state(|state| has(state, welcome.id)).await;
+
+    assert_eq!(client.disconnected().await.reason, Reason::Timeout);
+    assert!(has(&server.state(), welcome.id));
+
+    // From another address, as after a network change.
+    let mut again = server.client().await;
+    again.token = welcome.token;
+    let rejoined = again.join("alice").await;
+    assert_eq!(rejoined.id, welcome.id);
+    assert_eq!(rejoined.token, welcome.token);
+    let snapshot = again.snapshot(|snapshot| has(snapshot, welcome.id)).await;
+    assert_eq!(snapshot.players.len(), 1);
+}
+
+#[tokio::test]
+async fn taking_over_a_live_session_disconnects_the_old_address() {
+    let server = TestServer::start().await;
+    let mut client = server.client().await;
+    let welcome = client.join("alice").await;
+
+    let mut again = server.client().await;
+    again.token = welcome.token;
+    assert_eq!(again.join("alice").await.id, welcome.id);
+    assert_eq!(client.disconnected().await.reason, Reason::Kicked);
+}
+
+#[tokio::test]
+async fn suspended_sessions_ignore_everything_but_joins() {
+    let mut server = TestServer::with_config(Config {
+        connection_timeout: Duration::from_millis(200),
+        reconnect_grace: Duration::from_millis(300),
+        ..Default::default()
+    })
+    .await;
+    let mut client = server.client().await;
+    let id = client.join("alice").await.id;
+    server.wait_for_state(|state| has(state, id)).await;
+    assert_eq!(client.disconnected().await.reason, Reason::Timeout);
+    let suspended = server.state().players;
+
+    // Neither moves the player nor keeps the session from expiring.
+    let deadline = Instant::now() + common::TIMEOUT;
+    loop {
+        let state = server.state();
+        if !has(&state, id) {
+            break;
+        }
+        assert_eq!(state.players, suspended);
+        assert!(Instant::now() < deadline, "suspended session never expired");
+        client.hold((true, false, false, true)).await;
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+}
+
+#[tokio::test]
+async fn unknown_tokens_get_a_new_player() {
+    let server = TestServer::start().await;
+    let mut alice = server.client().await;
+    let a = alice.join("alice").await;
+
+    let mut bob = server.client().await;
+    bob.token = a.token.wrapping_add(1);
+    let b = bob.join("bob").await;
+    assert_ne!(b.id, a.id);
+    assert_ne!(b.token, a.token);
+}
+
+#[tokio::test]
+async fn expired_sessions_are_removed() {
+    let mut server = TestServer::with_config(Config {
+        connection_timeout: Duration::from_millis(200),
+        reconnect_grace: Duration::from_millis(300),
+        ..Default::default()
+    })
+    .await;
+    let mut client = server.client().await;
+    let id = client.join("alice").await.id;
+    server.wait_for_state(|state| has(state, id)).await;
+
+    assert_eq!(client.disconnected().await.reason, Reason::Timeout);
+    server.wait_for_state(|state| !has(state, id)).await;
+}
+
+#[tokio::test]
+async fn quitting_removes_the_player_straight_away() {
+    let mut server = TestServer::start().await;
+    let mut client = server.client().await;
+    let id = client.join("alice").await.id;
+    server.wait_for_state(|state| has(state, id)).await;
+
+    for _ in 0..DISCONNECT_REPEATS {
+        client
+            .send(Packet::Disconnect(Disconnect::with_message(
+                Reason::Quit,
+                "bye",
+            )))
+            .await;
+    }
+    // Long before the connection would time out.
+    server.wait_for_state(|state| !has(state, id)).await;
+}
+
+#[tokio::test]
+async fn other_protocol_versions_are_turned_away() {
+    let server = TestServer::start().await;
+    let mut client = server.client().await;
+
+    let mut frame = Frame::new();
+    frame.version = Frame::PROTOCOL_VERSION + 1;
+    frame.packet = Some(Packet::Join(Join::new(0, "alice")));
+    client.socket.send(&frame.to_bytes()).await.unwrap();
+
+    assert_eq!(client.disconnected().await.reason, Reason::VersionMismatch);
+    assert!(server.state().players.is_empty());
+}
+
+#[tokio::test]
+async fn version_replies_are_no_bigger_than_what_asked() {
+    let server = TestServer::start().await;
+    let mut client = server.client().await;
+
+    let mut frame = Frame::new();
+    frame.version = Frame::PROTOCOL_VERSION + 1;
+    client.socket.send(&frame.to_bytes()).await.unwrap();
+    assert!(client.drain(Duration::from_millis(200)).await.is_empty());
+
+    frame.packet = Some(Packet::Join(Join::new(0, "alice")));
+    let sent = frame.to_bytes();
+    client.socket.send(&sent).await.unwrap();
+    let mut buf = [0; 64];
+    let received = tokio::time::timeout(common::TIMEOUT, client.socket.recv(&mut buf))
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(received <= sent.len());
+}
+
+#[tokio::test]
+async fn full_servers_turn_joins_away() {
+    let server = TestServer::with_config(Config {
+        max_players: 1,
+        ..Default::default()
+    })
+    .await;
+    let mut alice = server.client().await;
+    let mut bob = server.client().await;
+    alice.join("alice").await;
+
+    bob.send(Packet::Join(Join::new(0, "bob"))).await;
+    let frames = bob.drain(Duration::from_millis(300)).await;
+    assert!(!frames
+        .iter()
+        .any(|frame| matches!(frame.packet, Some(Packet::Welcome(_)))));
+    assert!(frames.iter().any(|frame| matches!(
+        &frame.packet,
+        Some(Packet::Disconnect(disconnect)) if disconnect.reason == Reason::RoomFull
+    )));
+    assert_eq!(server.state().players.len(), 1);
+}
+
+#[tokio::test]
+async fn shutting_down_disconnects_everyone() {
+    let mut server = TestServer::start().await;
+    let mut alice = server.client().await;
+    let mut bob = server.client().await;
+    let a = alice.join("alice").await.id;
+    let b = bob.join("bob").await.id;
+    server
+        .wait_for_state(|state| has(state, a) && has(state, b))
+        .await;
+
+    server.shut_down().await;
+    for client in [&mut alice, &mut bob] {
+        assert_eq!(client.disconnected().await.reason, Reason::ServerShutdown);
+    }
+    // The last tick removed everyone.
+    assert!(server.state().players.is_empty());
+
+    let mut late = server.client().await;
+    late.send(Packet::Join(Join::new(0, "carol"))).await;
+    assert!(late.drain(Duration::from_millis(200)).await.is_empty());
+}